@@ -21,6 +21,12 @@ pub fn criterion_benchmark(c: &mut Criterion) {
 			criterion::black_box(42)
 		})
 	});
+	c.bench_function("econtext_data! key-values", |b| {
+		b.iter(|| {
+			econtext_data!("context", index = 42, attempt = 3);
+			criterion::black_box(42)
+		})
+	});
 	c.bench_function("econtext_function!", |b| {
 		b.iter(|| {
 			econtext_function!();
@@ -39,6 +45,12 @@ pub fn criterion_benchmark(c: &mut Criterion) {
 			criterion::black_box(42)
 		})
 	});
+	c.bench_function("scope()", |b| {
+		b.iter(|| {
+			let _guard = econtext::scope("context", 42);
+			criterion::black_box(42)
+		})
+	});
 }
 
 criterion_group!(benches, criterion_benchmark);
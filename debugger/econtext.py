@@ -0,0 +1,148 @@
+"""
+Print the error context of all threads from a live process or a core dump.
+
+Needs a binary built with the `core_dump` feature of econtext.
+
+GDB:
+    (gdb) source debugger/econtext.py
+    (gdb) econtext
+
+LLDB:
+    (lldb) command script import debugger/econtext.py
+    (lldb) econtext
+
+Reads the globals `ECONTEXT_CORE_DUMP_<major>_<minor>_<patch>`, one per version of econtext
+in the binary (see `src/core_dump.rs` for the layout).
+"""
+
+import re
+import struct
+
+SYMBOL_PATTERN = re.compile(r"\bECONTEXT_CORE_DUMP_\d+_\d+_\d+\b")
+MAGIC = b"ECONTEXT"
+LAYOUT_VERSION = 1
+
+# magic, layout_version, num_slots, slot_stride, name_size, text_size
+HEADER_FORMAT = "<8sIIIII"
+HEADER_SIZE = 32  # Including padding
+
+# thread_number, name_len, text_len, dropped_frames
+SLOT_HEADER_FORMAT = "<QIII"
+SLOT_NAME_OFFSET = 20
+
+
+def format_buffer(read_memory, address, symbol):
+    """
+    Return the error context of all threads as text.
+
+    `read_memory(address, size)` returns `bytes`.
+    """
+    header = read_memory(address, HEADER_SIZE)
+    magic, version, num_slots, slot_stride, name_size, text_size = struct.unpack_from(HEADER_FORMAT, header)
+    if magic != MAGIC:
+        return "{} does not look like an econtext core dump buffer".format(symbol)
+    if version != LAYOUT_VERSION:
+        return "Unsupported econtext core dump layout version {} (expected {})".format(version, LAYOUT_VERSION)
+
+    slots = read_memory(address + HEADER_SIZE, num_slots * slot_stride)
+    output = []
+    for i in range(num_slots):
+        slot = slots[i * slot_stride : (i + 1) * slot_stride]
+        thread_number, name_len, text_len, dropped_frames = struct.unpack_from(SLOT_HEADER_FORMAT, slot)
+        if thread_number == 0:
+            continue
+
+        name_start = SLOT_NAME_OFFSET
+        text_start = name_start + name_size
+        name = slot[name_start : name_start + min(name_len, name_size)].decode("utf-8", "replace")
+        text = slot[text_start : text_start + min(text_len, text_size)].decode("utf-8", "replace")
+
+        title = "thread #{}".format(thread_number)
+        if name:
+            title += ' "{}"'.format(name)
+        output.append("ERROR CONTEXT OF {} (outermost first):".format(title))
+        for line in text.splitlines():
+            output.append("  " + line)
+        if dropped_frames > 0:
+            output.append("  ... {} more frames did not fit ...".format(dropped_frames))
+        output.append("")
+
+    if not output:
+        return "No thread has any error context"
+    return "\n".join(output)
+
+
+def format_buffers(read_memory, symbols):
+    """
+    Return the error context in all buffers as text.
+
+    `symbols` maps symbol names to addresses.
+    """
+    if not symbols:
+        return "Could not find any ECONTEXT_CORE_DUMP_* symbol. Was the binary built with the `core_dump` feature of econtext?"
+    if len(symbols) == 1:
+        symbol, address = next(iter(symbols.items()))
+        return format_buffer(read_memory, address, symbol)
+
+    output = []
+    for symbol, address in sorted(symbols.items()):
+        output.append("{}:".format(symbol))
+        output.append(format_buffer(read_memory, address, symbol))
+    return "\n".join(output)
+
+
+# ----------------------------------------------------------------------------
+
+try:
+    import gdb
+except ImportError:
+    gdb = None
+
+if gdb is not None:
+
+    class EcontextCommand(gdb.Command):
+        """Print the econtext error context of all threads."""
+
+        def __init__(self):
+            super(EcontextCommand, self).__init__("econtext", gdb.COMMAND_DATA)
+
+        def invoke(self, arg, from_tty):
+            variables = gdb.execute("info variables ECONTEXT_CORE_DUMP_", to_string=True)
+            symbols = {}
+            for symbol in set(SYMBOL_PATTERN.findall(variables)):
+                symbols[symbol] = int(gdb.parse_and_eval("(unsigned long)&'{}'".format(symbol)))
+            inferior = gdb.selected_inferior()
+
+            def read_memory(address, size):
+                return bytes(inferior.read_memory(address, size))
+
+            print(format_buffers(read_memory, symbols))
+
+    EcontextCommand()
+
+
+def econtext_lldb(debugger, command, result, internal_dict):
+    """Print the econtext error context of all threads."""
+    import lldb
+
+    target = debugger.GetSelectedTarget()
+    process = target.GetProcess()
+    symbols = {}
+    for module in target.module_iter():
+        for symbol in module:
+            name = symbol.GetName()
+            if name and SYMBOL_PATTERN.fullmatch(name):
+                symbols[name] = symbol.GetStartAddress().GetLoadAddress(target)
+
+    def read_memory(address, size):
+        error = lldb.SBError()
+        data = process.ReadMemory(address, size, error)
+        if not error.Success():
+            raise RuntimeError(error.GetCString())
+        return data
+
+    result.AppendMessage(format_buffers(read_memory, symbols))
+
+
+def __lldb_init_module(debugger, internal_dict):
+    debugger.HandleCommand("command script add -f {}.econtext_lldb econtext".format(__name__))
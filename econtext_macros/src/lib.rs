@@ -0,0 +1,129 @@
+//! Procedural macros for [`econtext`](https://docs.rs/econtext).
+//!
+//! Use them through `econtext` with the `macros` feature enabled.
+
+#![allow(clippy::tabs_in_doc_comments)]
+
+use proc_macro::{Delimiter, Group, Ident, Literal, Punct, Spacing, Span, TokenStream, TokenTree};
+
+/// Fails compilation if the body of the annotated function does not call any `econtext` macro.
+///
+/// Use this to enforce that critical functions provide error context:
+///
+/// ``` ignore
+/// #[econtext::must_instrument]
+/// fn load_level(path: &str) {
+/// 	econtext::econtext_function_data!(path.to_owned());
+/// 	// …
+/// }
+/// ```
+///
+/// These count, including inside nested closures:
+/// * any macro whose name starts with `econtext`, and `etodo!` and `eunimplemented!`,
+/// * `econtext::scope(…)`,
+/// * the functions of `econtext::api`, when called through a path ending in `api::` (e.g. `api::context(…)`).
+///
+/// Functions imported by name (e.g. `use econtext::api::context;`) can't be recognized.
+#[proc_macro_attribute]
+pub fn must_instrument(attr: TokenStream, item: TokenStream) -> TokenStream {
+	if let Some(tt) = attr.into_iter().next() {
+		return with_error(item, tt.span(), "#[must_instrument] takes no arguments");
+	}
+
+	let tokens: Vec<TokenTree> = item.clone().into_iter().collect();
+
+	let name = tokens.windows(2).find_map(|pair| match pair {
+		[TokenTree::Ident(keyword), TokenTree::Ident(name)] if keyword.to_string() == "fn" => Some(name.clone()),
+		_ => None,
+	});
+	let name = match name {
+		Some(name) => name,
+		None => return with_error(item, Span::call_site(), "#[must_instrument] can only be used on functions"),
+	};
+
+	let body = tokens.iter().rev().find_map(|tt| match tt {
+		TokenTree::Group(group) if group.delimiter() == Delimiter::Brace => Some(group.stream()),
+		_ => None,
+	});
+	let body = match body {
+		Some(body) => body,
+		None => return item, // A function declaration without a body, e.g. in a trait.
+	};
+
+	if is_instrumented(body) {
+		item
+	} else {
+		let message = format!(
+			"`{}` is marked #[must_instrument] but does not call any econtext macro (e.g. `econtext_function!()`) or `econtext::api` function",
+			name
+		);
+		with_error(item, name.span(), &message)
+	}
+}
+
+/// The functions in `econtext::api`.
+const API_FUNCTIONS: &[&str] = &[
+	"context",
+	"context_data",
+	"boundary",
+	"boundary_data",
+	"function_scope",
+	"function_scope_data",
+	"todo",
+	"todo_data",
+	"unimplemented",
+	"unimplemented_data",
+];
+
+/// Is there a call to an econtext macro or function anywhere in these tokens? See [`must_instrument`].
+fn is_instrumented(stream: TokenStream) -> bool {
+	let tokens: Vec<TokenTree> = stream.into_iter().collect();
+	tokens.iter().enumerate().any(|(i, tt)| match tt {
+		TokenTree::Group(group) => is_instrumented(group.stream()),
+		TokenTree::Ident(ident) => {
+			let name = ident.to_string();
+			let is_macro = name.starts_with("econtext") || name == "etodo" || name == "eunimplemented";
+			// `Spacing::Alone`, so that e.g. `econtext_count != 0` doesn't count:
+			let bang = matches!(tokens.get(i + 1), Some(TokenTree::Punct(punct)) if punct.as_char() == '!' && punct.spacing() == Spacing::Alone);
+			(is_macro && bang) || path_function(&tokens[i + 1..]).is_some_and(|function| match name.as_str() {
+				"econtext" => function == "scope",
+				"api" => API_FUNCTIONS.contains(&function.as_str()),
+				_ => false,
+			})
+		}
+		_ => false,
+	})
+}
+
+/// The `function` of tokens starting with `::function`.
+fn path_function(tokens: &[TokenTree]) -> Option<String> {
+	match tokens {
+		[TokenTree::Punct(first), TokenTree::Punct(second), TokenTree::Ident(function), ..]
+			if first.as_char() == ':' && second.as_char() == ':' =>
+		{
+			Some(function.to_string())
+		}
+		_ => None,
+	}
+}
+
+/// Append a `compile_error!(message);` to the item.
+fn with_error(item: TokenStream, span: Span, message: &str) -> TokenStream {
+	let mut literal = Literal::string(message);
+	literal.set_span(span);
+	let mut args = Group::new(Delimiter::Parenthesis, TokenTree::Literal(literal).into());
+	args.set_span(span);
+	let mut bang = Punct::new('!', Spacing::Alone);
+	bang.set_span(span);
+	let mut semi = Punct::new(';', Spacing::Alone);
+	semi.set_span(span);
+
+	let mut output = item;
+	output.extend(vec![
+		TokenTree::Ident(Ident::new("compile_error", span)),
+		TokenTree::Punct(bang),
+		TokenTree::Group(args),
+		TokenTree::Punct(semi),
+	]);
+	output
+}
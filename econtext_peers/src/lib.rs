@@ -0,0 +1,36 @@
+//! The registry through which semver-incompatible versions of `econtext` linked into the same binary find each other.
+//!
+//! Every version of econtext depends on version 1 of this crate, which cargo unifies into a single copy,
+//! so they all share the same registry.
+//!
+//! Never make a breaking change to this crate: that would split the registry.
+
+use std::sync::Mutex;
+
+/// One version of econtext.
+#[derive(Clone, Copy)]
+pub struct Peer {
+	/// The version of econtext, e.g. `"0.2.0"`. There is at most one peer per version.
+	pub version: &'static str,
+
+	/// The error context of the current thread, one line per frame, as in `econtext_string()`. Must not panic.
+	pub render: fn() -> String,
+
+	/// Tells the panic hook of this version to not print anything for the current panic on the current thread.
+	pub suppress: fn(),
+}
+
+static PEERS: Mutex<Vec<Peer>> = Mutex::new(Vec::new());
+
+/// Add a peer, unless one with the same version is already registered.
+pub fn register(peer: Peer) {
+	let mut peers = PEERS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+	if !peers.iter().any(|existing| existing.version == peer.version) {
+		peers.push(peer);
+	}
+}
+
+/// All registered peers, in the order they registered.
+pub fn peers() -> Vec<Peer> {
+	PEERS.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+}
@@ -0,0 +1,240 @@
+//! Shipping reports to a local aggregation daemon, for fleets of worker processes whose stderr isn't collected.
+//!
+//! ```no_run
+//! # #[cfg(unix)]
+//! # fn main() -> std::io::Result<()> {
+//! econtext::add_panic_hook();
+//! econtext::agent::connect("/run/econtext.sock")?.dump_every(std::time::Duration::from_secs(60));
+//! # Ok(())
+//! # }
+//! # #[cfg(not(unix))]
+//! # fn main() {}
+//! ```
+//!
+//! ## Protocol
+//! The client only writes, and never reads. Each message is a JSON object on a single line, ending with `\n`.
+//! The `type` field tells what it is:
+//!
+//! * `{"type":"hello","protocol":1,"econtext":"0.2.0","pid":1234,"process":"worker"}`:
+//!   the first message of each connection. `process` is the file name of the executable.
+//! * `{"type":"report","pid":1234,"report":{…}}`: a report in [`crate::Format::Json`], each time one is printed (e.g. on panic).
+//! * `{"type":"dump","pid":1234,"time":"2026-10-16T09:41:07.123Z","threads":[{"thread":"'main'","frames":[…]}]}`:
+//!   the context of all registered threads (see [`crate::register_current_thread`]), sent by [`Agent::dump`].
+//!   Each thread is formatted like in `other_threads` of a report.
+//!
+//! The `protocol` version is bumped on incompatible changes. Daemons should ignore unknown fields and message types.
+//! If writing fails, the client reconnects (for [`connect`]) and tries once more, then drops the message.
+//! Writes to the socket time out after [`WRITE_TIMEOUT`], so a daemon that stops reading
+//! cannot block the panic hook for long: the connection is dropped and the message with it.
+
+use std::{
+	io::Write,
+	sync::{Arc, Condvar, Mutex},
+	thread::JoinHandle,
+	time::{Duration, Instant, SystemTime},
+};
+
+use crate::{
+	report::{write_json_frames, write_json_string, write_timestamp},
+	threads::lock,
+	Format, Sink,
+};
+
+/// Bump on incompatible changes to the protocol.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// How long a write to the socket of [`connect`] may block before the connection is dropped.
+pub const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A connection to the daemon, created by [`connect`] or [`Agent::from_stream`].
+///
+/// Cheap to clone, all clones share the connection.
+#[derive(Clone)]
+pub struct Agent {
+	connection: Arc<Mutex<Connection>>,
+}
+
+type Stream = Box<dyn Write + Send>;
+
+struct Connection {
+	stream: Option<Stream>,
+
+	/// How to reconnect, if possible.
+	reconnect: Option<Box<dyn Fn() -> std::io::Result<Stream> + Send>>,
+}
+
+/// Connect to the daemon listening on the Unix domain socket at `socket`,
+/// and send every report to it (in addition to the other sinks, see [`crate::add_output`]).
+#[cfg(unix)]
+pub fn connect(socket: impl Into<std::path::PathBuf>) -> std::io::Result<Agent> {
+	let socket = socket.into();
+	let reconnect = move || -> std::io::Result<Stream> {
+		let stream = std::os::unix::net::UnixStream::connect(&socket)?;
+		stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+		Ok(Box::new(stream))
+	};
+	let stream = reconnect()?;
+	Agent::start(Connection {
+		stream: Some(stream),
+		reconnect: Some(Box::new(reconnect)),
+	})
+}
+
+impl Agent {
+	/// Like [`connect`], but over any stream, e.g. a `TcpStream`. Does not reconnect.
+	///
+	/// Set a write timeout on the stream if it supports one, or a stuck reader will block every report.
+	pub fn from_stream(stream: impl Write + Send + 'static) -> std::io::Result<Self> {
+		Self::start(Connection {
+			stream: Some(Box::new(stream)),
+			reconnect: None,
+		})
+	}
+
+	fn start(connection: Connection) -> std::io::Result<Self> {
+		let agent = Self {
+			connection: Arc::new(Mutex::new(connection)),
+		};
+		agent.send_now(&hello())?;
+		crate::output::add_extra_output(ReportSink(agent.clone()), Format::Json);
+		Ok(agent)
+	}
+
+	/// Send a dump of all registered threads now.
+	pub fn dump(&self) {
+		let mut json = format!("{{\"type\":\"dump\",\"pid\":{},\"time\":\"", std::process::id());
+		let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+		write_timestamp(&mut json, now).ok();
+		json += "\",\"threads\":[";
+		let mut first = true;
+		crate::threads::for_each_other_thread(|thread| {
+			if !std::mem::take(&mut first) {
+				json.push(',');
+			}
+			json += "{\"thread\":";
+			write_json_string(&mut json, &thread.name);
+			json += ",\"frames\":";
+			write_json_frames(&mut json, &thread.frame_refs());
+			json.push('}');
+		});
+		json += "]}";
+		self.send(&json);
+	}
+
+	/// Send a dump of all registered threads every `interval`, from a background thread.
+	///
+	/// The thread calling this is not included unless it is registered and is not the background thread,
+	/// so call [`crate::register_current_thread`] on the threads you want to see.
+	///
+	/// The dumps go on until [`PeriodicDump::stop`] is called. Dropping the handle does not stop them.
+	pub fn dump_every(&self, interval: Duration) -> PeriodicDump {
+		let agent = self.clone();
+		let stop = Arc::new((Mutex::new(false), Condvar::new()));
+		let thread_stop = stop.clone();
+		let spawned = std::thread::Builder::new()
+			.name("econtext-agent".to_owned())
+			.spawn(move || {
+				let (stopped, changed) = &*thread_stop;
+				loop {
+					let deadline = Instant::now() + interval;
+					let mut state = lock(stopped);
+					while let Some(remaining) = deadline.checked_duration_since(Instant::now()).filter(|d| !d.is_zero()) {
+						if *state {
+							return;
+						}
+						state = match changed.wait_timeout(state, remaining) {
+							Ok((state, _)) => state,
+							Err(poisoned) => poisoned.into_inner().0,
+						};
+					}
+					if *state {
+						return;
+					}
+					drop(state);
+					agent.dump();
+				}
+			});
+		let thread = match spawned {
+			Ok(thread) => Some(thread),
+			Err(err) => {
+				eprintln!("econtext: failed to start the agent thread: {}", err);
+				None
+			}
+		};
+		PeriodicDump { stop, thread }
+	}
+
+	/// Send a message, reconnecting once if that fails.
+	fn send(&self, message: &str) {
+		if self.send_now(message).is_err() {
+			let mut connection = lock(&self.connection);
+			connection.stream = connection.reconnect.as_ref().and_then(|reconnect| reconnect().ok());
+			drop(connection);
+			if self.send_now(&hello()).is_ok() {
+				self.send_now(message).ok();
+			}
+		}
+	}
+
+	fn send_now(&self, message: &str) -> std::io::Result<()> {
+		let mut connection = lock(&self.connection);
+		let stream = connection
+			.stream
+			.as_mut()
+			.ok_or_else(|| std::io::Error::from(std::io::ErrorKind::NotConnected))?;
+		let result = stream
+			.write_all(message.as_bytes())
+			.and_then(|_| stream.write_all(b"\n"))
+			.and_then(|_| stream.flush());
+		if result.is_err() {
+			connection.stream = None;
+		}
+		result
+	}
+}
+
+/// The background thread of [`Agent::dump_every`].
+pub struct PeriodicDump {
+	/// Set to `true` to stop the thread.
+	stop: Arc<(Mutex<bool>, Condvar)>,
+
+	/// `None` if the thread could not be started.
+	thread: Option<JoinHandle<()>>,
+}
+
+impl PeriodicDump {
+	/// Stop sending dumps, and wait for the one being sent (if any) to finish.
+	pub fn stop(self) {
+		let (stopped, changed) = &*self.stop;
+		*lock(stopped) = true;
+		changed.notify_all();
+		if let Some(thread) = self.thread {
+			thread.join().ok();
+		}
+	}
+}
+
+fn hello() -> String {
+	let process = std::env::current_exe()
+		.ok()
+		.and_then(|exe| Some(exe.file_name()?.to_string_lossy().into_owned()))
+		.unwrap_or_default();
+	let mut json = format!(
+		"{{\"type\":\"hello\",\"protocol\":{},\"econtext\":\"{}\",\"pid\":{},\"process\":",
+		PROTOCOL_VERSION,
+		env!("CARGO_PKG_VERSION"),
+		std::process::id()
+	);
+	write_json_string(&mut json, &process);
+	json.push('}');
+	json
+}
+
+/// Wraps each report (in [`Format::Json`]) in a `report` message.
+struct ReportSink(Agent);
+
+impl Sink for ReportSink {
+	fn write(&self, report: &str) {
+		self.0.send(&format!("{{\"type\":\"report\",\"pid\":{},\"report\":{}}}", std::process::id(), report.trim_end()));
+	}
+}
@@ -0,0 +1,115 @@
+//! Functions and types that do what the macros do, for codebases that don't use macros (or generate code).
+//!
+//! | Macro | Function |
+//! |---|---|
+//! | `econtext!("saving")` | `let _scope = api::context("saving");` |
+//! | `econtext_data!("file", name)` | `let _scope = api::context_data("file", name);` |
+//! | `econtext_data!("file", %name)` | `let _scope = api::context_data("file", AsDisplay(name));` |
+//! | `econtext_data!("loading", path = p, attempt = n)` | `let _scope = api::context_data("loading", KeyValues::new((("path", p), ("attempt", n))));` |
+//! | `econtext_boundary!("request")` | `let _scope = api::boundary("request");` |
+//! | `econtext_boundary!("request", id)` | `let _scope = api::boundary_data("request", id);` |
+//! | `econtext_function!()` | `let _scope = api::function_scope(my_function);` |
+//! | `econtext_function_data!(arg)` | `let _scope = api::function_scope_data(my_function, arg);` |
+//! | `etodo!("export", pages)` | `api::todo_data("export", pages)` |
+//! | `eunimplemented!("export")` | `api::unimplemented("export")` |
+//!
+//! ```
+//! use econtext::api;
+//!
+//! fn process(file_name: &str) {
+//! 	let _scope = api::function_scope_data(process, api::AsDisplay(file_name.to_owned()));
+//! 	let _loading = api::context_data("loading", api::KeyValues::new((("attempt", 1),)));
+//! 	assert!(econtext::econtext_string().contains("process a.txt"));
+//! 	assert!(econtext::econtext_string().contains("loading attempt=1"));
+//! }
+//!
+//! process("a.txt");
+//! ```
+//!
+//! The scope lasts until the returned [`ScopeGuard`] is dropped, so bind it to a named variable
+//! (`let _ = api::context(…)` pops it right away).
+//!
+//! Unlike the frames from the macros, the frames pushed here have an empty module path,
+//! since only macros can know it. They get the file and line of the caller.
+//! Generated code that knows the module path can use [`DataScope`] directly, which is what the macros expand to,
+//! to get exactly the same frames: `DataScope::new(module_path, file, line, message, data).boundary().push()`.
+//!
+//! Without the `enabled` feature, or after `set_enabled(false)`, nothing is pushed,
+//! but unlike with the macros the arguments are still evaluated.
+
+use core::fmt::Debug;
+
+pub use crate::{format_with, AsDisplay, DataScope, EmptyDebug, FormatWith, FrameKind, KeyValues, ScopeGuard};
+
+/// A [`DataScope`] at the caller.
+#[track_caller]
+fn data_scope<Data: Debug>(kind: FrameKind, message: &'static str, data: Data) -> DataScope<Data> {
+	let location = core::panic::Location::caller();
+	DataScope::new("", location.file(), location.line(), message, data).kind(kind)
+}
+
+/// Provide a `&'static str` message as context, like [`crate::econtext!`].
+#[track_caller]
+pub fn context(message: &'static str) -> ScopeGuard {
+	data_scope(FrameKind::Message, message, EmptyDebug {}).push()
+}
+
+/// Provide a `&'static str` and some data as context, like [`crate::econtext_data!`].
+#[track_caller]
+pub fn context_data<Data: Debug + 'static>(message: &'static str, data: Data) -> ScopeGuard {
+	data_scope(FrameKind::Data, message, data).push()
+}
+
+/// Mark the start of a new logical section of the stack, like [`crate::econtext_boundary!`].
+#[track_caller]
+pub fn boundary(message: &'static str) -> ScopeGuard {
+	data_scope(FrameKind::Message, message, EmptyDebug {}).boundary().push()
+}
+
+/// Mark the start of a new logical section of the stack with some data, like [`crate::econtext_boundary!`].
+#[track_caller]
+pub fn boundary_data<Data: Debug + 'static>(message: &'static str, data: Data) -> ScopeGuard {
+	data_scope(FrameKind::Data, message, data).boundary().push()
+}
+
+/// Provide the name of `function` as context, like [`crate::econtext_function!`].
+///
+/// Pass the function itself, e.g. `api::function_scope(process)` or `api::function_scope(Self::process)`.
+#[track_caller]
+pub fn function_scope<F>(function: F) -> ScopeGuard {
+	function_scope_data(function, EmptyDebug {})
+}
+
+/// Provide the name of `function` and some data as context, like [`crate::econtext_function_data!`].
+#[track_caller]
+pub fn function_scope_data<F, Data: Debug + 'static>(function: F, data: Data) -> ScopeGuard {
+	data_scope(FrameKind::Function, crate::type_name_of(function), data).push()
+}
+
+/// Like `todo!()` with a message, after entering a scope with it, like [`crate::etodo!`].
+#[track_caller]
+pub fn todo(message: &'static str) -> ! {
+	todo_data(message, EmptyDebug {})
+}
+
+/// Like `todo!()` with a message, after entering a scope with it and some data, like [`crate::etodo!`].
+#[track_caller]
+pub fn todo_data<Data: Debug + 'static>(message: &'static str, data: Data) -> ! {
+	let _scope = data_scope(FrameKind::Todo, message, data).push();
+	crate::__print_unless_panic_hook();
+	core::todo!("{}", message)
+}
+
+/// Like `unimplemented!()` with a message, after entering a scope with it, like [`crate::eunimplemented!`].
+#[track_caller]
+pub fn unimplemented(message: &'static str) -> ! {
+	unimplemented_data(message, EmptyDebug {})
+}
+
+/// Like `unimplemented!()` with a message, after entering a scope with it and some data, like [`crate::eunimplemented!`].
+#[track_caller]
+pub fn unimplemented_data<Data: Debug + 'static>(message: &'static str, data: Data) -> ! {
+	let _scope = data_scope(FrameKind::Todo, message, data).push();
+	crate::__print_unless_panic_hook();
+	core::unimplemented!("{}", message)
+}
@@ -0,0 +1,83 @@
+//! Remembering the most recently exited scopes, for when the culprit is already gone.
+
+use std::{
+	cell::RefCell,
+	collections::VecDeque,
+	sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{Frame, FrameRef};
+
+static CAPACITY: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+	static BREADCRUMBS: RefCell<VecDeque<Frame>> = const { RefCell::new(VecDeque::new()) };
+}
+
+/// Remember the last `capacity` scopes that exited on each thread, and print them
+/// in a `RECENT CONTEXT` section on panic (most recent first).
+///
+/// This helps when the bad data was processed a few iterations before the panic:
+///
+/// ```
+/// econtext::set_breadcrumbs(32);
+/// for i in 0..10 {
+/// 	econtext::econtext_data!("i", i);
+/// }
+/// // On panic, "i 9", "i 8", … are printed as recent context.
+/// # econtext::set_breadcrumbs(0);
+/// ```
+///
+/// The default is `0`, which turns breadcrumbs off.
+/// When on, the data of each scope is formatted when it exits, which makes scopes a lot slower (around 100 ns instead of 15 ns).
+pub fn set_breadcrumbs(capacity: usize) {
+	let previous = CAPACITY.swap(capacity, Ordering::Relaxed);
+	if (previous > 0) != (capacity > 0) {
+		crate::stack::add_slow_path_reason(capacity > 0);
+	}
+}
+
+/// Called when a scope exits.
+#[inline]
+pub(crate) fn on_exit<'a>(frame: impl FnOnce() -> FrameRef<'a>) {
+	let capacity = CAPACITY.load(Ordering::Relaxed);
+	if capacity > 0 {
+		push(frame().to_frame(), capacity);
+	}
+}
+
+#[cold]
+fn push(frame: Frame, capacity: usize) {
+	BREADCRUMBS
+		.try_with(|breadcrumbs| {
+			if let Ok(mut breadcrumbs) = breadcrumbs.try_borrow_mut() {
+				while breadcrumbs.len() >= capacity {
+					breadcrumbs.pop_back();
+				}
+				breadcrumbs.push_front(frame);
+			}
+		})
+		.ok();
+}
+
+/// Forget the recently exited scopes of the current thread.
+pub(crate) fn clear() {
+	BREADCRUMBS
+		.try_with(|breadcrumbs| {
+			if let Ok(mut breadcrumbs) = breadcrumbs.try_borrow_mut() {
+				breadcrumbs.clear();
+			}
+		})
+		.ok();
+}
+
+/// The recently exited scopes of the current thread, most recent first.
+pub(crate) fn recent() -> Vec<Frame> {
+	BREADCRUMBS
+		.try_with(|breadcrumbs| {
+			let breadcrumbs = breadcrumbs.borrow();
+			let capacity = CAPACITY.load(Ordering::Relaxed);
+			breadcrumbs.iter().take(capacity).cloned().collect()
+		})
+		.unwrap_or_default()
+}
@@ -0,0 +1,252 @@
+//! Handing reports off to a background thread, for sinks that do slow I/O.
+
+use std::{
+	collections::VecDeque,
+	sync::{
+		atomic::{AtomicUsize, Ordering},
+		Arc, Condvar, Mutex, OnceLock,
+	},
+	thread::ThreadId,
+	time::{Duration, Instant},
+};
+
+use crate::{threads::lock, Frame, Sink};
+
+/// What [`BufferedSink`] does with a report when its queue is full.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DropPolicy {
+	/// Keep the queued reports, and drop the new one (default).
+	#[default]
+	DropNewest,
+
+	/// Drop the oldest queued report to make room for the new one.
+	DropOldest,
+}
+
+/// A [`Sink`] that writes to another sink from a background thread, through a bounded queue,
+/// so that the panic hook never blocks indefinitely on a slow destination (e.g. a network share).
+///
+/// ```
+/// let sink = econtext::BufferedSink::new(econtext::FileSink::new("crash_reports"))
+/// 	.with_capacity(8)
+/// 	.with_drop_policy(econtext::DropPolicy::DropOldest)
+/// 	.with_timeout(std::time::Duration::from_millis(200));
+/// econtext::add_output(sink, econtext::Format::Json);
+/// # econtext::set_output(econtext::Stderr);
+/// ```
+///
+/// Each [`Sink::write`] queues the report, then waits up to the timeout for it to be written,
+/// since the process often exits right after the panic hook.
+/// The thread is started on the first write.
+///
+/// Reports are passed on with their frames (see [`Sink::write_report`]), so e.g. `LogSink::per_frame` works behind a `BufferedSink`.
+///
+/// When the `BufferedSink` is dropped (e.g. replaced with [`crate::set_output`]), the thread writes the queued reports, then exits.
+pub struct BufferedSink<S: Sink + 'static> {
+	sink: Arc<S>,
+	queue: Arc<Queue>,
+	capacity: usize,
+	drop_policy: DropPolicy,
+	timeout: Duration,
+
+	/// The background thread, or `None` if it could not be started.
+	worker: OnceLock<Option<ThreadId>>,
+}
+
+struct Queue {
+	state: Mutex<QueueState>,
+	changed: Condvar,
+	dropped: AtomicUsize,
+}
+
+/// Each report gets a sequence number, and they are written (or dropped) in order.
+#[derive(Default)]
+struct QueueState {
+	/// With the frames, if written with [`Sink::write_report`].
+	reports: VecDeque<(u64, String, Option<Vec<Frame>>)>,
+
+	/// Sequence number of the last queued report.
+	pushed: u64,
+
+	/// Sequence number of the report being written.
+	writing: Option<u64>,
+
+	/// Sequence number of the last written report.
+	written: u64,
+
+	/// Sequence number of the last report dropped by [`DropPolicy::DropOldest`].
+	dropped: u64,
+
+	/// Set when the [`BufferedSink`] is dropped, telling the thread to exit once the queue is empty.
+	shutdown: bool,
+}
+
+impl QueueState {
+	fn is_done(&self, seq: u64) -> bool {
+		seq <= self.written || (seq <= self.dropped && self.writing != Some(seq))
+	}
+}
+
+impl<S: Sink + 'static> BufferedSink<S> {
+	/// Queue up to 32 reports, dropping new ones when full, and wait up to one second for each to be written.
+	pub fn new(sink: S) -> Self {
+		Self {
+			sink: Arc::new(sink),
+			queue: Arc::new(Queue {
+				state: Mutex::new(QueueState::default()),
+				changed: Condvar::new(),
+				dropped: AtomicUsize::new(0),
+			}),
+			capacity: 32,
+			drop_policy: DropPolicy::default(),
+			timeout: Duration::from_secs(1),
+			worker: OnceLock::new(),
+		}
+	}
+
+	/// Max number of reports (or sections, for streaming sinks) waiting to be written.
+	pub fn with_capacity(mut self, capacity: usize) -> Self {
+		self.capacity = capacity.max(1);
+		self
+	}
+
+	/// What to do when the queue is full.
+	pub fn with_drop_policy(mut self, drop_policy: DropPolicy) -> Self {
+		self.drop_policy = drop_policy;
+		self
+	}
+
+	/// How long [`Sink::write`] waits for the report to be written. Use `Duration::ZERO` to never wait.
+	pub fn with_timeout(mut self, timeout: Duration) -> Self {
+		self.timeout = timeout;
+		self
+	}
+
+	/// Number of reports dropped so far because the queue was full.
+	pub fn num_dropped(&self) -> usize {
+		self.queue.dropped.load(Ordering::Relaxed)
+	}
+
+	/// Wait up to `timeout` for all queued reports to be written. Returns `true` if they were.
+	pub fn flush(&self, timeout: Duration) -> bool {
+		let pushed = lock(&self.queue.state).pushed;
+		self.wait_until_done(pushed, timeout)
+	}
+
+	fn start_worker(&self) -> Option<ThreadId> {
+		let sink = self.sink.clone();
+		let queue = self.queue.clone();
+		let spawned = std::thread::Builder::new()
+			.name("econtext-sink".to_owned())
+			.spawn(move || loop {
+				let (seq, report, frames) = {
+					let mut state = lock(&queue.state);
+					loop {
+						if let Some((seq, report, frames)) = state.reports.pop_front() {
+							state.writing = Some(seq);
+							break (seq, report, frames);
+						}
+						if state.shutdown {
+							return;
+						}
+						state = queue.changed.wait(state).unwrap_or_else(|poisoned| poisoned.into_inner());
+					}
+				};
+				// Keep going if the sink panics (the panic hook then queues a report about it):
+				let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| match &frames {
+					Some(frames) => sink.write_report(&report, frames),
+					None => sink.write(&report),
+				}));
+				{
+					let mut state = lock(&queue.state);
+					state.writing = None;
+					state.written = seq;
+				}
+				queue.changed.notify_all();
+			});
+		match spawned {
+			Ok(handle) => Some(handle.thread().id()),
+			Err(err) => {
+				eprintln!("econtext: failed to start the sink thread, writing directly: {}", err);
+				None
+			}
+		}
+	}
+
+	/// Queue the report, and wait for it to be written. See [`Sink::write`].
+	fn enqueue(&self, report: &str, frames: Option<&[Frame]>) {
+		let Some(worker) = *self.worker.get_or_init(|| self.start_worker()) else {
+			match frames {
+				Some(frames) => self.sink.write_report(report, frames),
+				None => self.sink.write(report),
+			}
+			return;
+		};
+
+		let seq = {
+			let mut state = lock(&self.queue.state);
+			if state.reports.len() >= self.capacity {
+				self.queue.dropped.fetch_add(1, Ordering::Relaxed);
+				match self.drop_policy {
+					DropPolicy::DropNewest => return,
+					DropPolicy::DropOldest => {
+						if let Some((dropped, _, _)) = state.reports.pop_front() {
+							state.dropped = dropped;
+						}
+					}
+				}
+			}
+			state.pushed += 1;
+			let seq = state.pushed;
+			state.reports.push_back((seq, report.to_owned(), frames.map(<[Frame]>::to_vec)));
+			seq
+		};
+		self.queue.changed.notify_all();
+
+		// If the sink itself panicked, the worker can't write until we return:
+		if std::thread::current().id() != worker {
+			self.wait_until_done(seq, self.timeout);
+		}
+	}
+
+	/// Wait until the report `seq` (and all before it) is written or dropped, or `timeout` has passed.
+	fn wait_until_done(&self, seq: u64, timeout: Duration) -> bool {
+		let deadline = Instant::now() + timeout;
+		let mut state = lock(&self.queue.state);
+		while !state.is_done(seq) {
+			let Some(remaining) = deadline.checked_duration_since(Instant::now()).filter(|d| !d.is_zero()) else {
+				return false;
+			};
+			state = match self.queue.changed.wait_timeout(state, remaining) {
+				Ok((state, _)) => state,
+				Err(poisoned) => poisoned.into_inner().0,
+			};
+		}
+		true
+	}
+}
+
+impl<S: Sink + 'static> Drop for BufferedSink<S> {
+	fn drop(&mut self) {
+		lock(&self.queue.state).shutdown = true;
+		self.queue.changed.notify_all();
+	}
+}
+
+impl<S: Sink + 'static> Sink for BufferedSink<S> {
+	fn write(&self, report: &str) {
+		self.enqueue(report, None);
+	}
+
+	fn write_report(&self, report: &str, frames: &[Frame]) {
+		self.enqueue(report, Some(frames));
+	}
+
+	fn is_streaming(&self) -> bool {
+		self.sink.is_streaming()
+	}
+
+	fn supports_color(&self) -> bool {
+		self.sink.supports_color()
+	}
+}
@@ -0,0 +1,107 @@
+//! Helpers for build scripts.
+
+/// The environment variable that [`emit_cargo_features`] sets for the compilation of your crate.
+pub const CARGO_FEATURES_ENV_VAR: &str = "ECONTEXT_CARGO_FEATURES";
+
+/// Call this from the `build.rs` of your crate (with econtext in `[build-dependencies]`)
+/// so that [`crate::register_cargo_features!`] can include the enabled cargo features of your crate in reports.
+///
+/// ```no_run
+/// // In `fn main` of build.rs:
+/// econtext::build::emit_cargo_features();
+/// ```
+pub fn emit_cargo_features() {
+	println!("cargo:rustc-env={}={}", CARGO_FEATURES_ENV_VAR, cargo_features().join(","));
+}
+
+/// The enabled features of the crate being built, sorted.
+fn cargo_features() -> Vec<String> {
+	// Recent versions of cargo give us the exact names:
+	if let Ok(features) = std::env::var("CARGO_CFG_FEATURE") {
+		let mut features: Vec<String> = features
+			.split(',')
+			.filter(|feature| !feature.is_empty())
+			.map(ToOwned::to_owned)
+			.collect();
+		features.sort();
+		return features;
+	}
+
+	// Older versions only set `CARGO_FEATURE_<NAME>`, upper-cased and with `-` replaced by `_`:
+	let mut features: Vec<String> = std::env::vars_os()
+		.filter_map(|(name, _)| Some(name.to_str()?.strip_prefix("CARGO_FEATURE_")?.to_lowercase()))
+		.collect();
+	features.sort();
+	features
+}
+
+/// The file that [`emit_blame`] writes to `OUT_DIR`.
+#[cfg(feature = "blame")]
+pub const BLAME_FILE_NAME: &str = "econtext_blame.rs";
+
+/// Call this from the `build.rs` of your crate (with econtext and its `blame` feature in `[build-dependencies]`)
+/// so that [`crate::register_blame!`] can include the last commit of the file of each frame in JSON reports.
+///
+/// ```no_run
+/// // In `fn main` of build.rs:
+/// econtext::build::emit_blame();
+/// ```
+///
+/// This runs `git log` in the crate directory, and writes a table of the last commit of each `.rs` file to `OUT_DIR`.
+/// If git or the repository is missing (e.g. when built from crates.io), the table is empty.
+///
+/// It tells cargo to rerun the build script when `HEAD` moves, which replaces the default of
+/// rerunning it when any file in the package changes.
+#[cfg(feature = "blame")]
+pub fn emit_blame() {
+	let out_dir = std::env::var_os("OUT_DIR").expect("emit_blame must be called from a build script");
+	let commits = last_commits().unwrap_or_default();
+
+	let mut table = String::from("&[\n");
+	for (file, commit) in &commits {
+		table += &format!("\t({:?}, {:?}),\n", file, commit);
+	}
+	table += "]\n";
+	let path = std::path::Path::new(&out_dir).join(BLAME_FILE_NAME);
+	if let Err(err) = std::fs::write(&path, table) {
+		panic!("econtext: failed to write {}: {}", path.display(), err);
+	}
+
+	if let Some(git_dir) = git(&["rev-parse", "--absolute-git-dir"]) {
+		println!("cargo:rerun-if-changed={}/HEAD", git_dir);
+		if let Some(branch) = git(&["symbolic-ref", "-q", "HEAD"]) {
+			println!("cargo:rerun-if-changed={}/{}", git_dir, branch);
+		}
+	}
+}
+
+/// The last commit of each `.rs` file in the crate directory, relative to it, sorted by path.
+#[cfg(feature = "blame")]
+fn last_commits() -> Option<Vec<(String, String)>> {
+	let log = git(&["log", "--format=commit %H", "--name-only", "--relative", "--", "."])?;
+	let mut commits = std::collections::BTreeMap::new();
+	let mut commit = "";
+	for line in log.lines() {
+		if let Some(hash) = line.strip_prefix("commit ") {
+			commit = hash;
+		} else if line.ends_with(".rs") {
+			// The log is newest first:
+			commits.entry(line.to_owned()).or_insert_with(|| commit.to_owned());
+		}
+	}
+	Some(commits.into_iter().collect())
+}
+
+/// Run git in the crate directory, returning its trimmed output if it succeeded.
+#[cfg(feature = "blame")]
+fn git(args: &[&str]) -> Option<String> {
+	let mut command = std::process::Command::new("git");
+	if let Some(dir) = std::env::var_os("CARGO_MANIFEST_DIR") {
+		command.current_dir(dir);
+	}
+	let output = command.args(args).output().ok()?;
+	if !output.status.success() {
+		return None;
+	}
+	Some(String::from_utf8(output.stdout).ok()?.trim().to_owned())
+}
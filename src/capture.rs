@@ -0,0 +1,104 @@
+//! Handing the error context of a panic to crash reporters.
+
+use std::{
+	panic::PanicHookInfo,
+	sync::{Arc, RwLock},
+};
+
+use crate::{report::Report, Frame, FrameRef};
+
+/// Everything econtext knows about a panic, for forwarding to a crash reporter. See [`set_panic_capture`].
+#[derive(Clone, Debug)]
+pub struct PanicSnapshot {
+	/// The panic message, e.g. `"index out of bounds: the len is 3 but the index is 7"`.
+	pub message: String,
+
+	/// Where the panic happened.
+	pub location: Option<PanicLocation>,
+
+	/// The name of the panicking thread, if it has one.
+	pub thread: Option<String>,
+
+	/// The error context of the panicking thread, innermost first.
+	pub frames: Vec<Frame>,
+
+	/// Recently exited scopes, most recent first. See [`crate::set_breadcrumbs`].
+	pub recent: Vec<Frame>,
+
+	/// See [`crate::set_id_generator`].
+	pub report_id: Option<String>,
+
+	/// See [`crate::set_id_generator`].
+	pub fingerprint: Option<String>,
+
+	/// The enabled cargo features of each crate, see [`crate::register_cargo_features!`].
+	pub cargo_features: Vec<(&'static str, Vec<&'static str>)>,
+}
+
+/// The source location of a panic.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PanicLocation {
+	pub file: String,
+	pub line: u32,
+	pub column: u32,
+}
+
+type Handler = dyn Fn(&PanicSnapshot) + Send + Sync;
+
+static HANDLER: RwLock<Option<Arc<Handler>>> = RwLock::new(None);
+
+/// Call `handler` with a [`PanicSnapshot`] on every panic, e.g. to send it to Sentry or your own crash endpoint.
+///
+/// The handler is called by the econtext panic hook, right after the report is printed,
+/// so you also need to call [`crate::add_panic_hook`] (before or after this).
+/// To only capture, and not print, use `econtext::set_output(|_: &str| {})`.
+///
+/// ```
+/// econtext::set_panic_capture(|snapshot: &econtext::PanicSnapshot| {
+/// 	// Send snapshot.message, snapshot.frames, … to your crash reporter.
+/// });
+/// econtext::add_panic_hook();
+/// ```
+///
+/// This replaces any previous handler. The handler must not panic, since that aborts the process.
+pub fn set_panic_capture(handler: impl Fn(&PanicSnapshot) + Send + Sync + 'static) {
+	*HANDLER.write().unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(Arc::new(handler));
+}
+
+/// Called by the panic hook.
+pub(crate) fn on_panic(panic_info: &PanicHookInfo<'_>, report: &Report<'_>) {
+	let handler = HANDLER.read().unwrap_or_else(|poisoned| poisoned.into_inner()).clone();
+	if let Some(handler) = handler {
+		handler(&PanicSnapshot::new(panic_info, report));
+	}
+}
+
+impl PanicSnapshot {
+	fn new(panic_info: &PanicHookInfo<'_>, report: &Report<'_>) -> Self {
+		let payload = panic_info.payload();
+		let message = if let Some(message) = payload.downcast_ref::<&str>() {
+			(*message).to_owned()
+		} else if let Some(message) = payload.downcast_ref::<String>() {
+			message.clone()
+		} else {
+			"Box<dyn Any>".to_owned()
+		};
+
+		let (report_id, fingerprint) = report.ids.clone().unzip();
+
+		Self {
+			message,
+			location: panic_info.location().map(|location| PanicLocation {
+				file: location.file().to_owned(),
+				line: location.line(),
+				column: location.column(),
+			}),
+			thread: std::thread::current().name().map(ToOwned::to_owned),
+			frames: report.frames.iter().map(FrameRef::to_frame).collect(),
+			recent: report.recent.clone(),
+			report_id,
+			fingerprint,
+			cargo_features: report.cargo_features.clone(),
+		}
+	}
+}
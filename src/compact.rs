@@ -0,0 +1,188 @@
+//! Storing error context compactly, for when many copies are kept in memory.
+
+use std::{
+	collections::HashMap,
+	sync::{OnceLock, RwLock},
+	time::Duration,
+};
+
+use crate::{Frame, FrameKind, FrameRef};
+
+/// Everything about a frame except its data.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct Site {
+	module_path: &'static str,
+	file: &'static str,
+	line: u32,
+	message: &'static str,
+	kind: FrameKind,
+	boundary: bool,
+}
+
+/// All sites that have been stored in [`CompactFrames`] in this process.
+///
+/// The sites come from `&'static str`s in the binary, so this is bounded by the size of the program.
+#[derive(Default)]
+struct SiteTable {
+	sites: Vec<Site>,
+	ids: HashMap<Site, u32>,
+}
+
+fn site_table() -> &'static RwLock<SiteTable> {
+	static SITE_TABLE: OnceLock<RwLock<SiteTable>> = OnceLock::new();
+	SITE_TABLE.get_or_init(Default::default)
+}
+
+fn intern(frames: &[FrameRef<'_>]) -> Box<[u32]> {
+	let sites: Vec<Site> = frames
+		.iter()
+		.map(|frame| Site {
+			module_path: frame.module_path,
+			file: frame.file,
+			line: frame.line,
+			message: frame.message,
+			kind: frame.kind,
+			boundary: frame.boundary,
+		})
+		.collect();
+
+	{
+		// Usually all sites are known already:
+		let table = site_table().read().unwrap_or_else(|poisoned| poisoned.into_inner());
+		if let Some(ids) = sites.iter().map(|site| table.ids.get(site).copied()).collect() {
+			return ids;
+		}
+	}
+
+	let mut table = site_table().write().unwrap_or_else(|poisoned| poisoned.into_inner());
+	let SiteTable { sites: all_sites, ids } = &mut *table;
+	sites
+		.into_iter()
+		.map(|site| {
+			*ids.entry(site).or_insert_with(|| {
+				all_sites.push(site);
+				(all_sites.len() - 1) as u32
+			})
+		})
+		.collect()
+}
+
+// ----------------------------------------------------------------------------
+
+/// Frames of error context, stored compactly.
+///
+/// The `module file:line: message` of each frame is stored once per process in a shared table,
+/// so each frame only costs an index into it and the formatted data.
+/// This keeps the memory use bounded when retaining many copies of the context,
+/// e.g. in a [`crate::Snapshot`] per queued task or in a [`crate::EcontextError`] per error.
+///
+/// ```
+/// econtext::econtext_data!("request", 1234);
+/// let compact = econtext::CompactFrames::capture();
+/// assert_eq!(compact.frames()[0].data, "1234");
+/// ```
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct CompactFrames {
+	/// Indices into the site table, innermost first.
+	sites: Box<[u32]>,
+
+	/// The data of all frames, concatenated.
+	data: Box<str>,
+
+	/// Where the data of each frame ends in `data`.
+	data_ends: Box<[u32]>,
+
+	/// Microseconds since the unix epoch when each frame was entered (or `0`),
+	/// or empty if none of them were (see [`crate::GlobalConfig::with_timestamps`]).
+	entered: Box<[u64]>,
+}
+
+impl CompactFrames {
+	/// Capture the error context of the current thread.
+	pub fn capture() -> Self {
+		crate::stack::with_frames(Self::from_frames)
+	}
+
+	/// Innermost first.
+	pub fn from_frames(frames: &[FrameRef<'_>]) -> Self {
+		let mut data = String::new();
+		let mut data_ends = Vec::with_capacity(frames.len());
+		for frame in frames {
+			data += frame.data();
+			data_ends.push(data.len() as u32);
+		}
+		let entered = if frames.iter().any(|frame| frame.entered.is_some()) {
+			frames
+				.iter()
+				.map(|frame| frame.entered.map_or(0, |entered| entered.as_micros() as u64))
+				.collect()
+		} else {
+			Box::default()
+		};
+		Self {
+			sites: intern(frames),
+			data: data.into_boxed_str(),
+			data_ends: data_ends.into_boxed_slice(),
+			entered,
+		}
+	}
+
+	pub fn len(&self) -> usize {
+		self.sites.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.sites.is_empty()
+	}
+
+	/// The frames, innermost first.
+	pub fn frames(&self) -> Vec<Frame> {
+		self.frame_refs().iter().map(FrameRef::to_frame).collect()
+	}
+
+	/// The number of bytes allocated by this (not counting the shared site table).
+	pub fn heap_size(&self) -> usize {
+		std::mem::size_of_val(&*self.sites)
+			+ self.data.len()
+			+ std::mem::size_of_val(&*self.data_ends)
+			+ std::mem::size_of_val(&*self.entered)
+	}
+
+	pub(crate) fn frame_refs(&self) -> Vec<FrameRef<'_>> {
+		let table = site_table().read().unwrap_or_else(|poisoned| poisoned.into_inner());
+		(0..self.len()).map(|i| self.frame_ref(&table, i)).collect()
+	}
+
+	/// The message and data of each frame, outermost first, without allocating.
+	pub(crate) fn for_each_message(&self, mut f: impl FnMut(&'static str, &str) -> core::fmt::Result) -> core::fmt::Result {
+		for i in (0..self.len()).rev() {
+			// Don't hold the lock while calling `f`, which may write anywhere:
+			let message = site_table().read().unwrap_or_else(|poisoned| poisoned.into_inner()).sites[self.sites[i] as usize].message;
+			f(message, self.frame_data(i))?;
+		}
+		Ok(())
+	}
+
+	/// The frame at `i`, innermost first.
+	fn frame_ref(&self, table: &SiteTable, i: usize) -> FrameRef<'_> {
+		let site = table.sites[self.sites[i] as usize];
+		let entered = self.entered.get(i).copied().filter(|&entered| entered != 0);
+		FrameRef {
+			kind: site.kind,
+			boundary: site.boundary,
+			entered: entered.map(Duration::from_micros),
+			..FrameRef::formatted(site.module_path, site.file, site.line, site.message, self.frame_data(i))
+		}
+	}
+
+	fn frame_data(&self, i: usize) -> &str {
+		let start = if i == 0 { 0 } else { self.data_ends[i - 1] as usize };
+		&self.data[start..self.data_ends[i] as usize]
+	}
+}
+
+impl std::fmt::Debug for CompactFrames {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_list().entries(self.frames()).finish()
+	}
+}
@@ -0,0 +1,197 @@
+//! Configuring econtext from TOML, with the `config` feature.
+
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::{FileSink, Format, Position, Stderr, Style};
+
+/// All keys are optional. Leaving one out keeps the current setting.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+	enabled: Option<bool>,
+	panic_hook: Option<Position>,
+	format: Option<Format>,
+	style: Option<Style>,
+	stderr: Option<bool>,
+	max_report_size: Option<usize>,
+	max_frames: Option<usize>,
+	max_depth: Option<usize>,
+	max_data_size: Option<usize>,
+	ignore_modules: Option<Vec<String>>,
+	print_all_threads: Option<bool>,
+	breadcrumbs: Option<usize>,
+	thread_info: Option<bool>,
+	timestamps: Option<bool>,
+	redact: Option<Vec<String>>,
+	crash_reports: Option<CrashReports>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CrashReports {
+	dir: String,
+	format: Option<Format>,
+	public_key: Option<String>,
+}
+
+/// Why [`init_from_config`] or [`init_from_config_file`] failed.
+#[derive(Debug)]
+pub enum ConfigError {
+	Io(std::io::Error),
+	Toml(toml::de::Error),
+
+	/// E.g. an invalid public key.
+	Invalid(String),
+}
+
+impl std::fmt::Display for ConfigError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Io(err) => write!(f, "failed to read econtext config: {}", err),
+			Self::Toml(err) => write!(f, "invalid econtext config: {}", err),
+			Self::Invalid(err) => write!(f, "invalid econtext config: {}", err),
+		}
+	}
+}
+
+impl std::error::Error for ConfigError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			Self::Io(err) => Some(err),
+			Self::Toml(err) => Some(err),
+			Self::Invalid(_) => None,
+		}
+	}
+}
+
+/// Configure econtext from a TOML file. See [`init_from_config`].
+pub fn init_from_config_file(path: impl AsRef<Path>) -> Result<(), ConfigError> {
+	let toml = std::fs::read_to_string(path).map_err(ConfigError::Io)?;
+	init_from_config(&toml)
+}
+
+/// Configure econtext from TOML, so that reporting can be tuned without code changes.
+///
+/// ```
+/// econtext::init_from_config(r#"
+/// 	panic_hook = "before"  # Install the panic hook ("before" or "after" the previous hook)
+/// 	format = "text"        # "text", "single_line" or "json"
+/// 	style = "color"        # "plain", "color", "compact" or "compact_color"
+/// 	stderr = true          # Print reports to stderr
+/// 	max_report_size = 8192
+/// 	max_frames = 64
+/// 	max_depth = 256        # Ignore deeper scopes
+/// 	max_data_size = 512    # Cut off the data of each frame
+/// 	ignore_modules = ["noisy_dependency"]
+/// 	print_all_threads = true
+/// 	breadcrumbs = 32
+/// 	thread_info = true
+/// 	timestamps = true
+/// 	redact = ["password", "token"]
+///
+/// 	[crash_reports]        # Also write each report to a file
+/// 	dir = "crash_reports"
+/// 	format = "json"
+/// "#)
+/// .unwrap();
+/// # econtext::set_output(econtext::Stderr);
+/// ```
+///
+/// With the `age` feature, add `public_key = "age1…"` to `[crash_reports]` to encrypt the files (see `FileSink::encrypted`).
+///
+/// `ignore_modules` filters out scopes (see [`crate::set_ignored_modules`]).
+/// There are no levels to configure: econtext only reports on panic (or when asked to),
+/// and `LogSink` and `TracingSink` always emit at the error level.
+///
+/// All keys are optional, and leaving one out keeps the current setting,
+/// except that `stderr` and `crash_reports` together replace all sinks if either is given.
+/// Unknown keys are an error.
+pub fn init_from_config(toml: &str) -> Result<(), ConfigError> {
+	let config: Config = toml::from_str(toml).map_err(ConfigError::Toml)?;
+
+	// Validate before changing anything:
+	let file_sink = config.crash_reports.map(file_sink).transpose()?;
+
+	if let Some(enabled) = config.enabled {
+		crate::set_enabled(enabled);
+	}
+	if let Some(format) = config.format {
+		crate::set_format(format);
+	}
+	if let Some(style) = config.style {
+		crate::set_style(style);
+	}
+	if let Some(max_report_size) = config.max_report_size {
+		crate::set_max_report_size(max_report_size);
+	}
+	if let Some(max_frames) = config.max_frames {
+		crate::set_max_frames(max_frames);
+	}
+	if let Some(max_depth) = config.max_depth {
+		crate::set_max_depth(max_depth);
+	}
+	if let Some(max_data_size) = config.max_data_size {
+		crate::set_max_data_size(max_data_size);
+	}
+	if let Some(ignore_modules) = config.ignore_modules {
+		crate::set_ignored_modules(ignore_modules);
+	}
+	if let Some(print_all_threads) = config.print_all_threads {
+		crate::set_print_all_threads(print_all_threads);
+	}
+	if let Some(breadcrumbs) = config.breadcrumbs {
+		crate::set_breadcrumbs(breadcrumbs);
+	}
+	if let Some(thread_info) = config.thread_info {
+		crate::config().with_thread_info(thread_info);
+	}
+	if let Some(timestamps) = config.timestamps {
+		crate::config().with_timestamps(timestamps);
+	}
+	if let Some(redact) = config.redact {
+		crate::set_redacted_keys(redact);
+	}
+
+	if config.stderr.is_some() || file_sink.is_some() {
+		let stderr = config.stderr.unwrap_or(true);
+		match (stderr, file_sink) {
+			(true, None) => crate::set_output(Stderr),
+			(true, Some((sink, format))) => {
+				crate::set_output(Stderr);
+				crate::add_output(sink, format);
+			}
+			(false, Some((sink, format))) => {
+				crate::set_output(|_: &str| {});
+				crate::add_output(sink, format);
+			}
+			(false, None) => crate::set_output(|_: &str| {}),
+		}
+	}
+
+	if let Some(position) = config.panic_hook {
+		crate::add_panic_hook_with(position);
+	}
+
+	Ok(())
+}
+
+fn file_sink(crash_reports: CrashReports) -> Result<(FileSink, Format), ConfigError> {
+	let sink = FileSink::new(crash_reports.dir);
+	let format = crash_reports.format.unwrap_or_else(crate::report::format);
+
+	#[cfg(feature = "age")]
+	let sink = match crash_reports.public_key {
+		Some(public_key) => sink.encrypted(&public_key).map_err(|err| ConfigError::Invalid(err.to_owned()))?,
+		None => sink,
+	};
+	#[cfg(not(feature = "age"))]
+	if crash_reports.public_key.is_some() {
+		return Err(ConfigError::Invalid(
+			"crash_reports.public_key needs the `age` feature of econtext".to_owned(),
+		));
+	}
+
+	Ok((sink, format))
+}
@@ -0,0 +1,285 @@
+//! A global buffer with the error context of each thread, for post-mortem debugging.
+//!
+//! With the `core_dump` feature, each thread keeps a text copy of its stack
+//! (without the data, which would be too slow to format) in [`ECONTEXT_CORE_DUMP`].
+//! It is exported as `ECONTEXT_CORE_DUMP_<major>_<minor>_<patch>` with the version of this crate,
+//! so several versions of econtext can be linked into the same binary.
+//! It is updated on every push and pop, so the context can be recovered from a core dump
+//! or a live process even if no panic hook ran (e.g. on `abort()` or a segfault).
+//!
+//! Each thread that uses econtext gets a [`CoreDumpSlot`] until it exits.
+//! The text is one line per frame, outermost first: `module file:line: message\n`.
+//!
+//! `debugger/econtext.py` in the repository reads it from GDB or LLDB:
+//!
+//! ``` text
+//! (gdb) source debugger/econtext.py
+//! (gdb) econtext
+//! ```
+//!
+//! The script only relies on the header of [`CoreDumpBuffer`], so the sizes can change without breaking it.
+
+use std::{
+	cell::{Cell, UnsafeCell},
+	sync::atomic::{AtomicU32, AtomicU64, Ordering},
+};
+
+use crate::stack::Node;
+
+/// Max number of threads that can have a slot at the same time.
+pub const CORE_DUMP_THREADS: usize = 64;
+
+/// Max number of bytes of context per thread.
+pub const CORE_DUMP_SLOT_SIZE: usize = 2048;
+
+/// Max number of bytes of the thread name (truncated).
+pub const CORE_DUMP_NAME_SIZE: usize = 32;
+
+/// Bump when the layout changes in other ways than the sizes in the header.
+pub const CORE_DUMP_LAYOUT_VERSION: u32 = 1;
+
+/// The layout of [`ECONTEXT_CORE_DUMP`]. Do not change without updating `debugger/econtext.py`.
+#[repr(C)]
+pub struct CoreDumpBuffer {
+	/// Always `b"ECONTEXT"`.
+	pub magic: [u8; 8],
+
+	/// [`CORE_DUMP_LAYOUT_VERSION`].
+	pub layout_version: u32,
+
+	/// [`CORE_DUMP_THREADS`].
+	pub num_slots: u32,
+
+	/// Size of [`CoreDumpSlot`] in bytes, including padding.
+	pub slot_stride: u32,
+
+	/// [`CORE_DUMP_NAME_SIZE`].
+	pub name_size: u32,
+
+	/// [`CORE_DUMP_SLOT_SIZE`].
+	pub text_size: u32,
+
+	pub slots: [CoreDumpSlot; CORE_DUMP_THREADS],
+}
+
+/// The error context of one thread.
+///
+/// `name` starts at byte 20 and `text` right after it.
+#[repr(C)]
+pub struct CoreDumpSlot {
+	/// Zero if the slot is free, otherwise a unique number for the thread (starting at 1).
+	pub thread_number: AtomicU64,
+
+	/// Number of bytes used in `name`.
+	pub name_len: AtomicU32,
+
+	/// Number of bytes used in `text`.
+	pub text_len: AtomicU32,
+
+	/// Number of innermost frames that did not fit in `text`.
+	pub dropped_frames: AtomicU32,
+
+	pub name: UnsafeCell<[u8; CORE_DUMP_NAME_SIZE]>,
+
+	/// One line per frame, outermost first.
+	pub text: UnsafeCell<[u8; CORE_DUMP_SLOT_SIZE]>,
+}
+
+// Offsets that `debugger/econtext.py` relies on:
+const _: () = assert!(std::mem::offset_of!(CoreDumpBuffer, slots) == 32);
+const _: () = assert!(std::mem::offset_of!(CoreDumpSlot, name) == 20);
+
+// Safety: `name` and `text` are only accessed by the thread owning the slot (and by debuggers).
+unsafe impl Sync for CoreDumpSlot {}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY_SLOT: CoreDumpSlot = CoreDumpSlot {
+	thread_number: AtomicU64::new(0),
+	name_len: AtomicU32::new(0),
+	text_len: AtomicU32::new(0),
+	dropped_frames: AtomicU32::new(0),
+	name: UnsafeCell::new([0; CORE_DUMP_NAME_SIZE]),
+	text: UnsafeCell::new([0; CORE_DUMP_SLOT_SIZE]),
+};
+
+/// The error context of all threads, for debuggers. See the [module docs](self).
+#[export_name = concat!(
+	"ECONTEXT_CORE_DUMP_",
+	env!("CARGO_PKG_VERSION_MAJOR"),
+	"_",
+	env!("CARGO_PKG_VERSION_MINOR"),
+	"_",
+	env!("CARGO_PKG_VERSION_PATCH")
+)]
+#[used]
+pub static ECONTEXT_CORE_DUMP: CoreDumpBuffer = CoreDumpBuffer {
+	magic: *b"ECONTEXT",
+	layout_version: CORE_DUMP_LAYOUT_VERSION,
+	num_slots: CORE_DUMP_THREADS as u32,
+	slot_stride: std::mem::size_of::<CoreDumpSlot>() as u32,
+	name_size: CORE_DUMP_NAME_SIZE as u32,
+	text_size: CORE_DUMP_SLOT_SIZE as u32,
+	slots: [EMPTY_SLOT; CORE_DUMP_THREADS],
+};
+
+static NEXT_THREAD_NUMBER: AtomicU64 = AtomicU64::new(1);
+
+// ----------------------------------------------------------------------------
+
+/// Frees the slot of the thread when it exits.
+struct SlotHandle {
+	/// `None` before the first push, `Some(None)` if there were no free slots.
+	index: Cell<Option<Option<usize>>>,
+}
+
+impl Drop for SlotHandle {
+	fn drop(&mut self) {
+		if let Some(Some(index)) = self.index.get() {
+			let slot = &ECONTEXT_CORE_DUMP.slots[index];
+			slot.text_len.store(0, Ordering::Relaxed);
+			slot.thread_number.store(0, Ordering::Release);
+		}
+	}
+}
+
+thread_local! {
+	static SLOT: SlotHandle = const {
+		SlotHandle {
+			index: Cell::new(None),
+		}
+	};
+}
+
+/// Call `f` with the slot of the current thread, if it has one.
+///
+/// The first time, we allocate the slot and write the whole stack to it instead of calling `f`.
+fn with_slot(nodes: &[Node], f: impl FnOnce(&CoreDumpSlot)) {
+	let index = SLOT.try_with(|handle| match handle.index.get() {
+		Some(index) => index,
+		None => {
+			let index = allocate_slot();
+			handle.index.set(Some(index));
+			if let Some(index) = index {
+				rewrite_slot(&ECONTEXT_CORE_DUMP.slots[index], nodes);
+			}
+			None
+		}
+	});
+	if let Ok(Some(index)) = index {
+		f(&ECONTEXT_CORE_DUMP.slots[index]);
+	}
+}
+
+#[cold]
+fn allocate_slot() -> Option<usize> {
+	let thread_number = NEXT_THREAD_NUMBER.fetch_add(1, Ordering::Relaxed);
+	let index = ECONTEXT_CORE_DUMP.slots.iter().position(|slot| {
+		slot.thread_number
+			.compare_exchange(0, thread_number, Ordering::Acquire, Ordering::Relaxed)
+			.is_ok()
+	})?;
+
+	let slot = &ECONTEXT_CORE_DUMP.slots[index];
+	let thread = std::thread::current();
+	let name = thread.name().unwrap_or_default().as_bytes();
+	let name_len = name.len().min(CORE_DUMP_NAME_SIZE);
+	// Safety: we own the slot.
+	let slot_name = unsafe { &mut *slot.name.get() };
+	slot_name[..name_len].copy_from_slice(&name[..name_len]);
+	slot.name_len.store(name_len as u32, Ordering::Relaxed);
+	Some(index)
+}
+
+/// Appends whole lines to a slot, counting the lines that don't fit.
+struct SlotWriter<'a> {
+	slot: &'a CoreDumpSlot,
+	line_start: usize,
+	end: usize,
+	overflow: bool,
+}
+
+impl<'a> SlotWriter<'a> {
+	fn new(slot: &'a CoreDumpSlot) -> Self {
+		let len = slot.text_len.load(Ordering::Relaxed) as usize;
+		Self {
+			slot,
+			line_start: len,
+			end: len,
+			// Once a frame is dropped, all frames above it must be too:
+			overflow: slot.dropped_frames.load(Ordering::Relaxed) > 0,
+		}
+	}
+
+	fn end_line(&mut self) {
+		if self.overflow {
+			self.end = self.line_start;
+			self.slot.dropped_frames.fetch_add(1, Ordering::Relaxed);
+		} else {
+			self.slot.text_len.store(self.end as u32, Ordering::Relaxed);
+		}
+		self.line_start = self.end;
+	}
+}
+
+impl std::fmt::Write for SlotWriter<'_> {
+	fn write_str(&mut self, s: &str) -> std::fmt::Result {
+		for part in s.split_inclusive('\n') {
+			let bytes = part.as_bytes();
+			if !self.overflow && self.end + bytes.len() <= CORE_DUMP_SLOT_SIZE {
+				// Safety: we own the slot.
+				let text = unsafe { &mut *self.slot.text.get() };
+				text[self.end..self.end + bytes.len()].copy_from_slice(bytes);
+				self.end += bytes.len();
+			} else {
+				self.overflow = true;
+			}
+			if part.ends_with('\n') {
+				self.end_line();
+			}
+		}
+		Ok(())
+	}
+}
+
+fn rewrite_slot(slot: &CoreDumpSlot, nodes: &[Node]) {
+	slot.text_len.store(0, Ordering::Relaxed);
+	slot.dropped_frames.store(0, Ordering::Relaxed);
+	let mut writer = SlotWriter::new(slot);
+	for node in nodes {
+		node.write_sites(&mut writer);
+	}
+}
+
+// ----------------------------------------------------------------------------
+
+/// Called after an entry has been pushed to the top of `nodes`.
+pub(crate) fn on_push(nodes: &[Node]) {
+	with_slot(nodes, |slot| {
+		if let Some(node) = nodes.last() {
+			node.write_sites(&mut SlotWriter::new(slot));
+		}
+	});
+}
+
+/// Called when entries with `num_frames` frames in total have been popped, leaving `nodes`.
+pub(crate) fn on_pop(nodes: &[Node], num_frames: usize) {
+	with_slot(nodes, |slot| {
+		for _ in 0..num_frames {
+			let dropped = slot.dropped_frames.load(Ordering::Relaxed);
+			if dropped > 0 {
+				slot.dropped_frames.store(dropped - 1, Ordering::Relaxed);
+			} else {
+				// Safety: we own the slot.
+				let text = unsafe { &*slot.text.get() };
+				let len = slot.text_len.load(Ordering::Relaxed) as usize;
+				let start = text[..len.saturating_sub(1)].iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+				slot.text_len.store(start as u32, Ordering::Relaxed);
+			}
+		}
+	});
+}
+
+/// Called when the stack has been changed to `nodes` in other ways than a push or pop.
+pub(crate) fn on_reset(nodes: &[Node]) {
+	with_slot(nodes, |slot| rewrite_slot(slot, nodes));
+}
@@ -0,0 +1,97 @@
+//! Attaching the error context to errors, not just panics.
+
+use crate::{CompactFrames, Frame};
+
+/// An error together with the error context that was active when it was created.
+///
+/// Created with [`EcontextResultExt::with_econtext`].
+/// Its `Display` shows the error followed by the context.
+pub struct EcontextError<E> {
+	error: E,
+
+	frames: CompactFrames,
+}
+
+impl<E> EcontextError<E> {
+	/// Wrap the error together with the current error context.
+	pub fn new(error: E) -> Self {
+		Self {
+			error,
+			frames: CompactFrames::capture(),
+		}
+	}
+
+	pub fn error(&self) -> &E {
+		&self.error
+	}
+
+	pub fn into_error(self) -> E {
+		self.error
+	}
+
+	/// The error context when the error was created, innermost first.
+	pub fn frames(&self) -> Vec<Frame> {
+		self.frames.frames()
+	}
+
+	pub fn compact_frames(&self) -> &CompactFrames {
+		&self.frames
+	}
+
+	/// The error context when the error was created, in the same format as [`crate::econtext_string`].
+	pub fn context_string(&self) -> String {
+		crate::report::stack_text(&self.frames.frame_refs(), crate::Style::Plain)
+	}
+}
+
+impl<E: std::fmt::Debug> std::fmt::Debug for EcontextError<E> {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("EcontextError")
+			.field("error", &self.error)
+			.field("frames", &self.frames)
+			.finish()
+	}
+}
+
+impl<E: std::fmt::Display> std::fmt::Display for EcontextError<E> {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		self.error.fmt(f)?;
+		if !self.frames.is_empty() {
+			write!(f, "\n\nERROR CONTEXT:\n{}", self.context_string())?;
+		}
+		Ok(())
+	}
+}
+
+impl<E: std::error::Error + 'static> std::error::Error for EcontextError<E> {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		Some(&self.error)
+	}
+}
+
+/// Attach the current error context to the error of a [`Result`].
+///
+/// ```
+/// use econtext::EcontextResultExt as _;
+///
+/// fn load(path: &str) -> Result<String, econtext::EcontextError<std::io::Error>> {
+/// 	econtext::econtext_function_data!(path.to_owned());
+/// 	std::fs::read_to_string(path).with_econtext()
+/// }
+///
+/// let error = load("does_not_exist.txt").unwrap_err();
+/// assert!(error.to_string().contains("does_not_exist.txt"));
+/// ```
+///
+/// To include the context in errors of your own (e.g. with `anyhow` or `thiserror`),
+/// use [`crate::econtext_string`] when creating the error.
+pub trait EcontextResultExt<T, E> {
+	/// On `Err`, capture the current error context and wrap it together with the error.
+	fn with_econtext(self) -> Result<T, EcontextError<E>>;
+}
+
+impl<T, E> EcontextResultExt<T, E> for Result<T, E> {
+	fn with_econtext(self) -> Result<T, EcontextError<E>> {
+		self.map_err(EcontextError::new)
+	}
+}
@@ -0,0 +1,39 @@
+//! Leaving out the scopes of noisy modules.
+
+use std::sync::{
+	atomic::{AtomicBool, Ordering},
+	RwLock,
+};
+
+static ANY_IGNORED: AtomicBool = AtomicBool::new(false);
+static IGNORED_MODULES: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+/// Don't record the scopes entered in these modules (and their submodules).
+///
+/// Useful when a dependency instruments more than you care about, e.g. `set_ignored_modules(["hyper"])`
+/// ignores scopes from `hyper` and `hyper::client`, but not from `hyper_util`.
+/// Frames without a module path (see [`crate::api`]) are never ignored. Replaces the previous list.
+pub fn set_ignored_modules<S: Into<String>>(modules: impl IntoIterator<Item = S>) {
+	let modules: Vec<String> = modules.into_iter().map(Into::into).collect();
+	let any_ignored = !modules.is_empty();
+	*IGNORED_MODULES.write().unwrap_or_else(|poisoned| poisoned.into_inner()) = modules;
+	crate::stack::store_setting(&ANY_IGNORED, any_ignored, true);
+}
+
+#[inline]
+pub(crate) fn is_ignored(module_path: &str) -> bool {
+	ANY_IGNORED.load(Ordering::Relaxed) && is_ignored_slow(module_path)
+}
+
+#[cold]
+fn is_ignored_slow(module_path: &str) -> bool {
+	IGNORED_MODULES
+		.read()
+		.unwrap_or_else(|poisoned| poisoned.into_inner())
+		.iter()
+		.any(|ignored| {
+			module_path
+				.strip_prefix(ignored.as_str())
+				.is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+		})
+}
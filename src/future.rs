@@ -0,0 +1,60 @@
+//! Keeping the error context when running futures.
+
+use std::{
+	future::Future,
+	pin::Pin,
+	sync::Arc,
+	task::{Context, Poll, Wake, Waker},
+	thread::Thread,
+};
+
+use crate::stack;
+
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+	fn wake(self: Arc<Self>) {
+		self.0.unpark();
+	}
+
+	fn wake_by_ref(self: &Arc<Self>) {
+		self.0.unpark();
+	}
+}
+
+/// Run a future to completion on the current thread, like `pollster::block_on`.
+///
+/// The error context of the caller stays installed while the future is polled,
+/// so a panic inside the future prints it:
+///
+/// ```
+/// econtext::econtext!("Loading shaders");
+/// let source = econtext::block_on(async {
+/// 	assert!(econtext::econtext_string().contains("Loading shaders"));
+/// 	"void main() {}"
+/// });
+/// ```
+///
+/// Scopes entered inside the future that are still active when it yields are
+/// suspended until it is polled again, so they never linger on the stack of the caller.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+	let mut future = Box::pin(future);
+	let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+	let mut context = Context::from_waker(&waker);
+
+	let caller_len = stack::len();
+	let mut suspended = Vec::new(); // Scopes suspended inside the future
+
+	loop {
+		stack::resume(suspended);
+
+		let poll = Pin::as_mut(&mut future).poll(&mut context);
+
+		suspended = stack::suspend(caller_len);
+
+		match poll {
+			Poll::Ready(output) => return output,
+			Poll::Pending => std::thread::park(),
+		}
+	}
+}
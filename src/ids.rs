@@ -0,0 +1,106 @@
+//! Identifiers in reports, for correlating them with logs and traces and for grouping crashes.
+
+use std::{
+	collections::hash_map::RandomState,
+	hash::{BuildHasher, Hasher},
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc, RwLock,
+	},
+	time::SystemTime,
+};
+
+use crate::FrameRef;
+
+/// Generates the identifiers included in reports. Set it with [`set_id_generator`].
+///
+/// Implement this to use the trace ids and crash fingerprints of the rest of your observability stack.
+pub trait IdGenerator: Send + Sync {
+	/// A unique id for a report, e.g. the trace id of the current request.
+	fn report_id(&self) -> String;
+
+	/// Identifies the place of the crash, so that reports of the same bug can be grouped.
+	///
+	/// Called with the frames of the panicking thread, innermost first.
+	fn fingerprint(&self, frames: &[FrameRef<'_>]) -> String;
+}
+
+/// The [`IdGenerator`] used by `set_id_generator(DefaultIdGenerator)`.
+///
+/// The report id is 16 random hexadecimal digits.
+/// The fingerprint is a 64-bit FNV-1a hash (as 16 hexadecimal digits) of the `module file:line: message`
+/// of all frames, ignoring the data, so it is the same across runs and machines for the same build.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DefaultIdGenerator;
+
+impl IdGenerator for DefaultIdGenerator {
+	fn report_id(&self) -> String {
+		static COUNTER: AtomicU64 = AtomicU64::new(0);
+		let mut hasher = RandomState::new().build_hasher();
+		hasher.write_u128(
+			SystemTime::now()
+				.duration_since(SystemTime::UNIX_EPOCH)
+				.map_or(0, |duration| duration.as_nanos()),
+		);
+		hasher.write_u32(std::process::id());
+		hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+		format!("{:016x}", hasher.finish())
+	}
+
+	fn fingerprint(&self, frames: &[FrameRef<'_>]) -> String {
+		let mut hash = Fnv1a::default();
+		for frame in frames {
+			hash.write(frame.module_path.as_bytes());
+			hash.write(frame.file.as_bytes());
+			hash.write(&frame.line.to_le_bytes());
+			hash.write(frame.message.as_bytes());
+		}
+		format!("{:016x}", hash.0)
+	}
+}
+
+/// Stable across Rust versions, unlike `DefaultHasher`.
+struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+	fn default() -> Self {
+		Self(0xcbf2_9ce4_8422_2325)
+	}
+}
+
+impl Fnv1a {
+	fn write(&mut self, bytes: &[u8]) {
+		// A separator, so that e.g. "ab" + "c" differs from "a" + "bc":
+		for &byte in bytes.iter().chain(&[0xff]) {
+			self.0 ^= byte as u64;
+			self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+		}
+	}
+}
+
+// ----------------------------------------------------------------------------
+
+static ID_GENERATOR: RwLock<Option<Arc<dyn IdGenerator>>> = RwLock::new(None);
+
+/// Include a report id and a fingerprint in each report (default: off).
+///
+/// ```
+/// econtext::set_id_generator(econtext::DefaultIdGenerator);
+/// ```
+///
+/// In the text formats this adds a line like `Report id: 5f0e2c3a9b1d4e67, fingerprint: 0c9a6e1f3b2d4a58`,
+/// and in [`crate::Format::Json`] the fields `report_id` and `fingerprint`.
+pub fn set_id_generator(generator: impl IdGenerator + 'static) {
+	*ID_GENERATOR.write().unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(Arc::new(generator));
+}
+
+/// Undo [`set_id_generator`].
+pub(crate) fn clear_id_generator() {
+	*ID_GENERATOR.write().unwrap_or_else(|poisoned| poisoned.into_inner()) = None;
+}
+
+/// The report id and fingerprint for a report with these frames, if [`set_id_generator`] has been called.
+pub(crate) fn report_ids(frames: &[FrameRef<'_>]) -> Option<(String, String)> {
+	let generator = ID_GENERATOR.read().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()?;
+	Some((generator.report_id(), generator.fingerprint(frames)))
+}
@@ -0,0 +1,125 @@
+//! Setting up everything with one call.
+
+use std::path::PathBuf;
+
+use crate::{FileSink, Format, Style};
+
+/// What [`init_with`] sets up. Start from [`Options::default`], which is what [`init`] uses.
+///
+/// ```
+/// econtext::init_with(econtext::Options {
+/// 	json_dir: Some("crash_reports".into()),
+/// 	breadcrumbs: 0,
+/// 	..Default::default()
+/// });
+/// # econtext::set_output(econtext::Stderr);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Options {
+	/// Print reports to stderr (or the browser console on `wasm32` with the `wasm` feature).
+	///
+	/// Default: `true`.
+	pub stderr: bool,
+
+	/// How the reports look, see [`crate::set_style`]. Colors are only used when stderr is a terminal.
+	///
+	/// Default: [`Style::Color`].
+	pub style: Style,
+
+	/// Also write each report as JSON to a new file in this directory, see [`FileSink`].
+	///
+	/// Default: `None`.
+	pub json_dir: Option<PathBuf>,
+
+	/// Also print the context of all registered threads on panic, see [`crate::set_print_all_threads`].
+	///
+	/// Default: `true`.
+	pub all_threads: bool,
+
+	/// Number of recently exited scopes to print, see [`crate::set_breadcrumbs`].
+	///
+	/// Default: `16`.
+	pub breadcrumbs: usize,
+
+	/// Add a report id and a fingerprint to each report, see [`crate::set_id_generator`].
+	///
+	/// Default: `true`.
+	pub ids: bool,
+
+	/// Print the name and id of the panicking thread, see [`crate::GlobalConfig::with_thread_info`].
+	///
+	/// Default: `true`.
+	pub thread_info: bool,
+
+	/// Record when each scope was entered, see [`crate::GlobalConfig::with_timestamps`].
+	///
+	/// Default: `false`.
+	pub timestamps: bool,
+}
+
+impl Default for Options {
+	fn default() -> Self {
+		Self {
+			stderr: true,
+			style: Style::Color,
+			json_dir: None,
+			all_threads: true,
+			breadcrumbs: 16,
+			ids: true,
+			thread_info: true,
+			timestamps: false,
+		}
+	}
+}
+
+/// Set up econtext for an application: the panic hook, printing to stderr (in color if it is a terminal),
+/// the context of all threads, recent context, report ids, and thread info.
+///
+/// Same as `init_with(Options::default())`. Call it first thing in `main`:
+///
+/// ```
+/// econtext::init();
+/// econtext::econtext!("While running");
+/// ```
+///
+/// Note that the breadcrumbs make scopes slower. Use [`init_with`] to turn them off.
+pub fn init() {
+	init_with(Options::default());
+}
+
+/// Like [`init`], but with your own [`Options`].
+///
+/// This replaces all sinks, and calling it again changes the settings but not the panic hook.
+pub fn init_with(options: Options) {
+	let Options {
+		stderr,
+		style,
+		json_dir,
+		all_threads,
+		breadcrumbs,
+		ids,
+		thread_info,
+		timestamps,
+	} = options;
+
+	if stderr {
+		crate::output::set_default_output();
+	} else {
+		crate::set_output(|_: &str| {});
+	}
+	if let Some(json_dir) = json_dir {
+		crate::add_output(FileSink::new(json_dir), Format::Json);
+	}
+	crate::set_style(style);
+
+	crate::set_print_all_threads(all_threads);
+	crate::set_breadcrumbs(breadcrumbs);
+	if ids {
+		crate::set_id_generator(crate::DefaultIdGenerator);
+	} else {
+		crate::ids::clear_id_generator();
+	}
+	crate::config().with_thread_info(thread_info).with_timestamps(timestamps);
+
+	crate::add_panic_hook();
+}
@@ -1,6 +1,6 @@
 //! # econtext: fast and simple error context on panics.
 //!
-//! Calling an `econtext!` macro adds a scope to a thread-local linked list. If there is a `panic!()`
+//! Calling an `econtext!` macro pushes a scope to a thread-local stack. If there is a `panic!()`
 //! while the scope is active, the data/message provided to the `econtext!` macro will be printed.
 //!
 //! This thus provides an opt-in stack trace with optional data (e.g. the values of function arguments).
@@ -11,10 +11,11 @@
 //! * To provide something similar to a stack trace where a real stack trace is not available (e.g. in some WASM contexts)
 //! * To print a shorter and more readable stack trace for when the real stack trace is too long and winding.
 //!
-//! The overhead of calling an `excontext` macro is around 15ns on a 2020 MacBook Pro.
+//! The overhead of calling an `econtext` macro is around 15 ns with the default settings (see `cargo bench`).
+//! Recent context, timestamps, validators, ignored modules, registered threads and the `core_dump` feature all make it slower (around 75 to 300 ns).
 //!
 //! ## Example
-//! ``` rust
+//! ``` rust,should_panic
 //! use econtext::*;
 //!
 //! fn main() {
@@ -46,83 +47,717 @@
 //!   my_module src/main.rs:10: main::run
 //!   my_module src/main.rs:5: While running
 //! ```
+//!
+//! ## Setup
+//! Instead of `econtext::add_panic_hook()`, call `econtext::init()` to also print the context of all threads, recent context, and report ids (see below).
+//! Use `econtext::init_with(econtext::Options { … })` to e.g. also write JSON crash reports to a directory.
+//!
+//! ## Formatting data
+//! Data is formatted with `Debug`. Prefix it with `%` to use `Display` instead, e.g. `econtext_data!("file", %path)`,
+//! or use `econtext::format_with(value, |value, f| …)` for custom formatting.
+//! Several named values can be given as `econtext_data!("loading", path = %path, attempt = attempt)`.
+//! Use `econtext::set_redacted_keys(["password"])` to keep the values of sensitive keys out of reports.
+//! In debug builds, `econtext::add_key_validator("request_id", |value| …)` and `econtext::add_message_validator` check recorded data (e.g. that a request id is a UUID),
+//! and panic on invalid data to catch instrumentation drift in tests.
+//! Data of up to four words (e.g. a number, a `String`, or one key-value pair with a number) is stored in the stack itself, larger data is moved to the heap.
+//! The `Debug` and `Drop` impls of the data don't see any context, and scopes they enter are ignored.
+//!
+//! ## Limits
+//! So that a dependency that instruments aggressively can't blow up the panic path of your application, these limits apply by default:
+//! at most 1024 nested scopes per thread (deeper ones are ignored, and counted in a `deeper scopes not recorded` frame), 1 KiB of formatted data per frame, and 256 KiB per report.
+//! Change them with `econtext::set_max_depth`, `econtext::set_max_data_size` and `econtext::set_max_report_size` (use `usize::MAX` for no limit).
+//! Use `econtext::set_ignored_modules(["noisy_dependency"])` to not record the scopes of some modules at all.
+//!
+//! ## Styles
+//! Call `econtext::set_style(econtext::Style::Color)` (or set the environment variable `ECONTEXT_STYLE=color`) to print reports with ANSI colors when stderr is a terminal,
+//! or use `Style::Compact` to leave out the module and shorten file paths.
+//!
+//! ## Threads
+//! The error context is per thread. Use `econtext::spawn` instead of `std::thread::spawn` to start a thread with a copy of the context of the parent thread,
+//! or `econtext::Snapshot` to do the same for thread pools.
+//! Call `econtext::set_print_all_threads(true)` to also print the context of all registered threads on panic.
+//! Call `econtext::disable_for_current_thread()` on threads that run untrusted code (e.g. plugins) to keep them from recording or seeing any context.
+//!
+//! ## Unfinished code
+//! Use `etodo!("exporting to PDF", pages = n)` and `eunimplemented!(…)` instead of `todo!()` and `unimplemented!()`
+//! to also get a frame with the message and data, and a report even without the panic hook.
+//!
+//! ## Sections
+//! Use `econtext_boundary!("request", id)` to mark the start of a logical section of the stack (e.g. one request in a server).
+//! Sections are separated by a line in the report.
+//!
+//! ## Recent context
+//! Call `econtext::set_breadcrumbs(32)` to also print the last 32 scopes that exited on the panicking thread.
+//! This is off by default, since it makes scopes slower.
+//!
+//! ## Report ids
+//! Call `econtext::set_id_generator(econtext::DefaultIdGenerator)` to add a unique report id and a crash fingerprint (for grouping reports of the same bug) to each report.
+//! Implement `econtext::IdGenerator` to use the ids of your own tracing and crash reporting instead.
+//!
+//! ## Compile-time features
+//! Call `econtext::build::emit_cargo_features()` from your `build.rs` and `econtext::register_cargo_features!()` in `main`
+//! to include the enabled cargo features of your crate in each report.
+//! With the `blame` feature, call `econtext::build::emit_blame()` and `econtext::register_blame!()` the same way
+//! to include the last git commit of the file of each frame in JSON reports.
+//!
+//! ## Crash reporters
+//! Call `econtext::set_panic_capture(|snapshot| …)` to get the panic message, location, thread name, and error context as an `econtext::PanicSnapshot`,
+//! e.g. to forward them to Sentry or your own crash endpoint, instead of parsing what is printed.
+//! Each frame in JSON reports (and `econtext::Frame`) has a `kind` (`message`, `data`, `function`, or `todo`) telling which macro entered it.
+//! Use `econtext::with_sink(sink, || …)` to send the reports of panics caught within the closure to a specific sink, e.g. for request-scoped logging or to check reports in tests.
+//! Wrap sinks that do slow I/O in `econtext::BufferedSink::new(sink)` to write from a background thread through a bounded queue, so the panic hook never blocks indefinitely.
+//!
+//! ## Aggregation daemon
+//! Call `econtext::agent::connect("/run/econtext.sock")` to also send reports (and, with `.dump_every(interval)`, the context of all registered threads) to a local daemon,
+//! for fleets of worker processes whose stderr isn't collected. The protocol is documented in `econtext::agent`.
+//!
+//! ## Thread info and timestamps
+//! Call `econtext::config().with_thread_info(true).with_timestamps(true)` to print the name and id of the panicking thread, and when each scope was entered,
+//! so reports can be correlated with logs.
+//!
+//! ## Without macros
+//! Every macro has a function equivalent in `econtext::api`, e.g. `let _scope = econtext::api::context_data("file", name);`, for codebases that don't use macros or generate code.
+//!
+//! ## Errors
+//! Use `.with_econtext()` (from `econtext::EcontextResultExt`) on a `Result` to attach the current error context to the error.
+//! Use `econtext::current()` in log statements and debugger watch expressions, e.g. `log::debug!("retrying in {}", econtext::current())`, to print the context on one line (`main > i 4`) without allocating.
+//!
+//! ## Enforcing instrumentation
+//! With the `macros` feature, mark critical functions with `#[econtext::must_instrument]` to get a compile error if they do not call any `econtext` macro.
+//!
+//! ## `no_std`
+//! Turn off the default `std` feature to use econtext in `no_std` environments with `alloc` (e.g. embedded firmware).
+//! Then there is no panic hook, and a single stack is used, stored in the `econtext::StackSlot` you give to `econtext::set_stack_slot` (nothing is recorded until then).
+//! Call `econtext::write_econtext(&mut serial)` from your `#[panic_handler]` to print the context.
+//!
+//! ## Cargo features
+//! * `enabled` (default): turn this off to compile all econtext macros to nothing. See also `econtext::set_enabled`.
+//! * `std` (default): see `no_std` above.
+//! * `age`: encrypt the reports written by `econtext::FileSink` with an age public key.
+//! * `blame`: `econtext::register_blame!`, see "Compile-time features" above.
+//! * `config`: `econtext::init_from_config` to configure econtext with TOML, e.g. from a file that ops can edit.
+//! * `core_dump`: keep a copy of the context of each thread in a global buffer (`ECONTEXT_CORE_DUMP_<version>`) that can be read from core dumps.
+//! * `log`: `econtext::LogSink`, which emits reports as `log` records.
+//! * `macros`: the `#[must_instrument]` attribute.
+//! * `serde`: `serde::Serialize` for `econtext::Frame`.
+//! * `tracing`: `econtext::TracingSink`, which emits reports as `tracing` events.
+//! * `wasm`: on `wasm32-unknown-unknown`, print to the browser console (`econtext::Console`) and export `econtextString()` to JavaScript.
+//!
+//! ## Minimum supported Rust version
+//! econtext needs Rust 1.82 or newer (see `rust-version` in `Cargo.toml`), e.g. for `std::panic::PanicHookInfo`.
 
-use std::{cell::RefCell, fmt::Debug};
+#![allow(clippy::tabs_in_doc_comments)]
+#![cfg_attr(not(feature = "std"), no_std)]
 
-// Points to the top of the error context stack
-thread_local! {
-	pub static ERROR_STACK: RefCell<Option<*const dyn Entry>> = RefCell::new(None);
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod agent;
+pub mod api;
+#[cfg(feature = "std")]
+mod breadcrumbs;
+#[cfg(feature = "std")]
+mod buffered_sink;
+#[cfg(feature = "std")]
+pub mod build;
+#[cfg(feature = "std")]
+mod capture;
+#[cfg(feature = "std")]
+mod compact;
+#[cfg(feature = "core_dump")]
+pub mod core_dump;
+#[cfg(feature = "config")]
+mod config;
+#[cfg(feature = "std")]
+mod error;
+#[cfg(feature = "std")]
+mod future;
+#[cfg(feature = "std")]
+mod filter;
+#[cfg(feature = "std")]
+mod ids;
+#[cfg(feature = "std")]
+mod init;
+#[cfg(feature = "log")]
+mod log_sink;
+#[cfg(feature = "std")]
+mod metadata;
+#[cfg(feature = "std")]
+mod output;
+#[cfg(feature = "std")]
+mod peers;
+#[cfg(feature = "std")]
+mod redact;
+mod report;
+mod stack;
+#[cfg(not(feature = "std"))]
+mod stack_slot;
+#[cfg(feature = "std")]
+mod threads;
+#[cfg(feature = "std")]
+mod validate;
+#[cfg(feature = "tracing")]
+mod tracing_sink;
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+mod wasm;
+
+#[cfg(feature = "std")]
+pub use breadcrumbs::set_breadcrumbs;
+#[cfg(feature = "std")]
+pub use buffered_sink::{BufferedSink, DropPolicy};
+#[cfg(feature = "std")]
+pub use capture::{set_panic_capture, PanicLocation, PanicSnapshot};
+#[cfg(feature = "std")]
+pub use compact::CompactFrames;
+#[cfg(feature = "config")]
+pub use config::{init_from_config, init_from_config_file, ConfigError};
+#[cfg(feature = "std")]
+pub use error::{EcontextError, EcontextResultExt};
+#[cfg(feature = "std")]
+pub use filter::set_ignored_modules;
+#[cfg(feature = "std")]
+pub use future::block_on;
+#[cfg(feature = "std")]
+pub use ids::{set_id_generator, DefaultIdGenerator, IdGenerator};
+#[cfg(feature = "std")]
+pub use init::{init, init_with, Options};
+#[cfg(feature = "log")]
+pub use log_sink::LogSink;
+#[cfg(feature = "blame")]
+pub use metadata::add_blame;
+#[cfg(feature = "std")]
+pub use metadata::{add_cargo_features, config, GlobalConfig};
+#[cfg(feature = "std")]
+pub use output::{add_output, set_output, with_sink, FileSink, Sink, Stderr};
+#[cfg(feature = "std")]
+pub use redact::set_redacted_keys;
+pub use report::{set_max_data_size, set_max_frames, Style, DEFAULT_MAX_DATA_SIZE};
+#[cfg(feature = "std")]
+pub use report::{set_format, set_max_report_size, set_style, Format, DEFAULT_MAX_REPORT_SIZE};
+pub use stack::{set_max_depth, ScopeGuard, DEFAULT_MAX_DEPTH};
+#[cfg(not(feature = "std"))]
+pub use stack::Stack;
+#[cfg(not(feature = "std"))]
+pub use stack_slot::{set_stack_slot, SingleCoreSlot, StackSlot};
+#[cfg(feature = "std")]
+pub use threads::{register_current_thread, set_print_all_threads, spawn, Snapshot, SnapshotGuard};
+#[cfg(feature = "tracing")]
+pub use tracing_sink::TracingSink;
+#[cfg(feature = "std")]
+pub use validate::{add_key_validator, add_message_validator};
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+pub use wasm::{econtext_string_js, Console};
+
+#[cfg(feature = "macros")]
+pub use econtext_macros::must_instrument;
+
+use alloc::{borrow::ToOwned, string::String, vec::Vec};
+use core::{
+	cell::OnceCell,
+	fmt::Debug,
+	sync::atomic::{AtomicBool, Ordering},
+	time::Duration,
+};
+use stack::is_disabled_for_current_thread;
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Turn collecting error context on or off at runtime (default: on).
+///
+/// Scopes entered while this is off are not pushed, and cost only a relaxed atomic load.
+/// Scopes that were entered before are still popped as usual.
+///
+/// To remove econtext completely at compile time, turn off the default `enabled` cargo feature instead.
+pub fn set_enabled(enabled: bool) {
+	stack::store_setting(&ENABLED, enabled, false);
+}
+
+/// See [`set_enabled`] and [`disable_for_current_thread`].
+pub fn is_enabled() -> bool {
+	cfg!(feature = "enabled") && ENABLED.load(Ordering::Relaxed) && !is_disabled_for_current_thread()
 }
 
-/// The trait for an entry in the stack
-pub trait Entry {
-	fn write(&self, writer: &mut dyn std::fmt::Write);
+/// Turn econtext off for the rest of the life of the current thread, e.g. a thread that runs untrusted plugin code.
+///
+/// Scopes entered on this thread are not pushed, and [`Snapshot::enter`] does nothing.
+/// The context of this thread is empty everywhere (e.g. in [`econtext_string`] and [`Snapshot::capture`]),
+/// including any scopes entered before this call, and a panic on this thread prints nothing,
+/// not even the context of other threads.
+/// If the thread was registered (see [`register_current_thread`]), it is unregistered,
+/// so panics on other threads don't print its context either.
+///
+/// ```
+/// std::thread::spawn(|| {
+/// 	econtext::disable_for_current_thread();
+/// 	econtext::econtext!("Running plugin");
+/// 	assert_eq!(econtext::econtext_string(), "");
+/// })
+/// .join()
+/// .unwrap();
+/// ```
+///
+/// This cannot be undone, so code running on the thread cannot turn econtext back on.
+#[cfg(feature = "std")]
+pub fn disable_for_current_thread() {
+	stack::disable_for_current_thread();
+	breadcrumbs::clear();
+}
+
+/// Write `module file:line: message` on one line, without allocating.
+#[cfg(feature = "core_dump")]
+fn write_site(writer: &mut dyn core::fmt::Write, module_path: &str, file: &str, line: u32, message: &str) {
+	write!(writer, "{} {}:{}: ", module_path, file, line).ok();
+	for (i, part) in message.split('\n').enumerate() {
+		if i > 0 {
+			writer.write_char(' ').ok();
+		}
+		writer.write_str(part).ok();
+	}
+	writer.write_char('\n').ok();
 }
 
 // ----------------------------------------------------------------------------
 
-/// What is put in a stack frame that uses the macros.
-pub struct DataScope<Data> {
-	/// Linked list: pointer to the previous entry.
-	previous: Option<*const dyn Entry>,
+/// An owned copy of one stack frame, with the data already formatted.
+///
+/// With the `serde` feature this serializes the same way as in [`econtext_json`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Frame {
+	#[cfg_attr(feature = "serde", serde(rename = "module"))]
+	pub module_path: &'static str,
+	pub file: &'static str,
+	pub line: u32,
+	pub message: &'static str,
+
+	/// The `Debug` formatting of the data (empty if there is no data).
+	pub data: String,
+
+	/// Which macro entered the frame.
+	pub kind: FrameKind,
+
+	/// Set by [`econtext_boundary!`]: this frame starts a new logical section of the stack (e.g. a request).
+	#[cfg_attr(feature = "serde", serde(skip_serializing_if = "core::ops::Not::not"))]
+	pub boundary: bool,
+
+	/// When the scope was entered, as time since the unix epoch. See `GlobalConfig::with_timestamps`.
+	#[cfg_attr(
+		feature = "serde",
+		serde(skip_serializing_if = "Option::is_none", serialize_with = "report::serialize_timestamp")
+	)]
+	pub entered: Option<Duration>,
+}
+
+/// Which macro entered a frame, so that tools can e.g. tell function scopes from ad-hoc messages.
+///
+/// The functions in [`api`] give the same kind as the corresponding macros.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize), serde(rename_all = "snake_case"))]
+#[non_exhaustive]
+pub enum FrameKind {
+	/// [`econtext!`], or a frame made some other way (e.g. with [`FrameRef::new`]).
+	#[default]
+	Message,
+
+	/// [`econtext_data!`] or [`scope`].
+	Data,
+
+	/// [`econtext_function!`] or [`econtext_function_data!`].
+	Function,
+
+	/// [`etodo!`] or [`eunimplemented!`].
+	Todo,
+}
+
+impl FrameKind {
+	/// The name used in JSON, e.g. `"function"`.
+	pub fn as_str(self) -> &'static str {
+		match self {
+			Self::Message => "message",
+			Self::Data => "data",
+			Self::Function => "function",
+			Self::Todo => "todo",
+		}
+	}
+}
+
+impl core::fmt::Display for Frame {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		FrameRef::from(self).fmt(f)
+	}
+}
+
+/// A stack frame borrowed from the error context stack (or from a [`Frame`]).
+///
+/// The data is formatted when first needed, and then cached,
+/// so that rendering the same frame in several formats only calls its `Debug` impl once.
+pub struct FrameRef<'a> {
+	pub module_path: &'static str,
+	pub file: &'static str,
+	pub line: u32,
+	pub message: &'static str,
+	data: FrameData<'a>,
+
+	/// See [`Frame::kind`].
+	pub kind: FrameKind,
+
+	/// See [`Frame::boundary`].
+	pub boundary: bool,
+
+	/// See [`Frame::entered`].
+	pub entered: Option<Duration>,
+}
+
+enum FrameData<'a> {
+	Debug(&'a dyn Debug, OnceCell<String>),
+	Formatted(&'a str),
+}
 
+impl<'a> FrameRef<'a> {
+	/// A frame whose data will be formatted with `Debug` when needed.
+	pub fn new(module_path: &'static str, file: &'static str, line: u32, message: &'static str, data: &'a dyn Debug) -> Self {
+		Self {
+			module_path,
+			file,
+			line,
+			message,
+			data: FrameData::Debug(data, OnceCell::new()),
+			kind: FrameKind::Message,
+			boundary: false,
+			entered: None,
+		}
+	}
+
+	/// A frame whose data is already formatted.
+	pub fn formatted(module_path: &'static str, file: &'static str, line: u32, message: &'static str, data: &'a str) -> Self {
+		Self {
+			module_path,
+			file,
+			line,
+			message,
+			data: FrameData::Formatted(data),
+			kind: FrameKind::Message,
+			boundary: false,
+			entered: None,
+		}
+	}
+
+	/// The `Debug` formatting of the data (empty if there is no data).
+	pub fn data(&self) -> &str {
+		match &self.data {
+			FrameData::Debug(data, formatted) => formatted.get_or_init(|| report::format_data(data)),
+			FrameData::Formatted(data) => data,
+		}
+	}
+
+	/// Same `file:line: message`, ignoring the data.
+	pub(crate) fn is_same_site(&self, other: &FrameRef<'_>) -> bool {
+		self.line == other.line && self.file == other.file && self.message == other.message
+	}
+
+	pub fn to_frame(&self) -> Frame {
+		Frame {
+			module_path: self.module_path,
+			file: self.file,
+			line: self.line,
+			message: self.message,
+			data: self.data().to_owned(),
+			kind: self.kind,
+			boundary: self.boundary,
+			entered: self.entered,
+		}
+	}
+}
+
+impl<'a> From<&'a Frame> for FrameRef<'a> {
+	fn from(frame: &'a Frame) -> Self {
+		Self {
+			kind: frame.kind,
+			boundary: frame.boundary,
+			entered: frame.entered,
+			..Self::formatted(frame.module_path, frame.file, frame.line, frame.message, &frame.data)
+		}
+	}
+}
+
+impl core::fmt::Display for FrameRef<'_> {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		if let Some(entered) = self.entered {
+			report::write_timestamp(f, entered)?;
+			f.write_str(" ")?;
+		}
+		if !self.module_path.is_empty() {
+			write!(f, "{} ", self.module_path)?;
+		}
+		write!(f, "{}:{}: {} {}", self.file, self.line, self.message, self.data())
+	}
+}
+
+// ----------------------------------------------------------------------------
+
+/// A scope that is about to be pushed. Used by the macros.
+pub struct DataScope<Data: Debug> {
 	module_path: &'static str,
 	file: &'static str,
 	line: u32,
 
 	message: &'static str,
 	data: Data,
-}
 
-impl<Data: Debug> Entry for DataScope<Data> {
-	fn write(&self, writer: &mut dyn std::fmt::Write) {
-		write!(
-			writer,
-			"  {} {}:{}: {} {:?}\n",
-			self.module_path, self.file, self.line, self.message, self.data
-		)
-		.ok();
-		unsafe {
-			if let Some(previous) = self.previous.as_ref().and_then(|p| p.as_ref()) {
-				previous.write(writer);
-			}
-		}
-	}
+	kind: FrameKind,
+	boundary: bool,
 }
 
 impl<Data: Debug> DataScope<Data> {
 	pub fn new(module_path: &'static str, file: &'static str, line: u32, message: &'static str, data: Data) -> Self {
-		let previous = ERROR_STACK.with(|stack| stack.borrow().clone());
 		DataScope {
-			previous,
 			module_path,
 			file,
 			line,
 			message,
 			data,
+			kind: FrameKind::Data,
+			boundary: false,
 		}
 	}
-}
 
-impl<Data> Drop for DataScope<Data> {
-	fn drop(&mut self) {
-		ERROR_STACK.with(|stack| *stack.borrow_mut() = self.previous);
+	/// Which macro this scope is from. The default is [`FrameKind::Data`].
+	pub fn kind(mut self, kind: FrameKind) -> Self {
+		self.kind = kind;
+		self
+	}
+
+	/// Mark this scope as the start of a new logical section of the stack. See [`econtext_boundary!`].
+	pub fn boundary(mut self) -> Self {
+		self.boundary = true;
+		self
+	}
+
+	/// Push this scope to the top of the stack until the returned guard is dropped,
+	/// unless econtext is off (see [`set_enabled`]) or the module is ignored (see [`set_ignored_modules`]).
+	///
+	/// Small data (up to four words, e.g. a `String`) is stored in the stack itself,
+	/// larger data is moved to the heap.
+	#[inline]
+	pub fn push(self) -> ScopeGuard
+	where
+		Data: 'static,
+	{
+		if !cfg!(feature = "enabled") {
+			return ScopeGuard::NOT_PUSHED;
+		}
+		if stack::is_slow_path() {
+			return self.push_slow();
+		}
+		stack::push_fast(|| stack::NodeKind::Scope(self.into_scope()))
+	}
+
+	/// [`Self::push`] when econtext is off, or any of the settings that need more work than a push are on.
+	#[inline(never)]
+	fn push_slow(self) -> ScopeGuard
+	where
+		Data: 'static,
+	{
+		if !ENABLED.load(Ordering::Relaxed) {
+			return ScopeGuard::NOT_PUSHED;
+		}
+		#[cfg(feature = "std")]
+		if filter::is_ignored(self.module_path) {
+			return ScopeGuard::NOT_PUSHED;
+		}
+		#[cfg(feature = "std")]
+		validate::check_message(self.message, self.file, self.line, &self.data);
+		#[allow(unused_mut)]
+		let mut scope = self.into_scope();
+		#[cfg(feature = "std")]
+		{
+			scope.entered = metadata::now_micros();
+		}
+		stack::push_slow(stack::NodeKind::Scope(scope))
+	}
+
+	#[inline]
+	fn into_scope(self) -> stack::Scope
+	where
+		Data: 'static,
+	{
+		stack::Scope {
+			module_path: self.module_path,
+			file: self.file,
+			line: self.line,
+			message: self.message,
+			kind: self.kind,
+			boundary: self.boundary,
+			#[cfg(feature = "std")]
+			entered: 0,
+			data: stack::ScopeData::new(self.data),
+		}
 	}
 }
 
 // ----------------------------------------------------------------------------
 
+/// Provide a `&'static str` and some data as context until the returned guard is dropped.
+///
+/// This is a function alternative to [`econtext_data!`] that can be used in expressions,
+/// and the returned guard can be stored and moved around (e.g. into a struct).
+///
+/// ```
+/// # fn parse(_: &str) {}
+/// let file_names = vec!["a.txt".to_owned(), "b.txt".to_owned()];
+/// file_names.iter().for_each(|file_name| {
+/// 	let _guard = econtext::scope("parsing", file_name.clone());
+/// 	parse(file_name);
+/// });
+/// ```
+///
+/// The frame has the file and line of the caller, but no module path.
+///
+/// Guards should be dropped on the same thread, in the reverse order they were created, see [`ScopeGuard`].
+#[track_caller]
+pub fn scope<Data: Debug + 'static>(message: &'static str, data: Data) -> ScopeGuard {
+	let location = core::panic::Location::caller();
+	DataScope::new("", location.file(), location.line(), message, data).push()
+}
+
+// ----------------------------------------------------------------------------
+
 /// Used internally when not having any data in a context scope.
 pub struct EmptyDebug {}
-impl std::fmt::Debug for EmptyDebug {
-	fn fmt(&self, _f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl core::fmt::Debug for EmptyDebug {
+	fn fmt(&self, _f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
 		Ok(())
 	}
 }
 
+/// Formats the data with `Display` instead of `Debug`, so e.g. strings are printed without quotes.
+///
+/// This is what the `%` sigil of the macros uses: `econtext_data!("path", %path.display().to_string())`.
+pub struct AsDisplay<T>(pub T);
+
+impl<T: core::fmt::Display> Debug for AsDisplay<T> {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		self.0.fmt(f)
+	}
+}
+
+/// Formats the data with a closure, for types that implement neither `Debug` nor `Display`
+/// (or when they print too much). Created with [`format_with`].
+pub struct FormatWith<T, F>(T, F);
+
+/// Use `format` to format `value` in the error context.
+///
+/// ```
+/// struct Matrix {
+/// 	rows: usize,
+/// 	cols: usize,
+/// 	values: Vec<f32>,
+/// }
+///
+/// let matrix = Matrix { rows: 2, cols: 2, values: vec![0.0; 4] };
+/// econtext::econtext_data!(
+/// 	"matrix",
+/// 	econtext::format_with(matrix, |m: &Matrix, f| write!(f, "{}x{}", m.rows, m.cols))
+/// );
+/// assert!(econtext::econtext_string().contains("matrix 2x2"));
+/// ```
+pub fn format_with<T, F>(value: T, format: F) -> FormatWith<T, F>
+where
+	F: Fn(&T, &mut core::fmt::Formatter<'_>) -> core::fmt::Result,
+{
+	FormatWith(value, format)
+}
+
+impl<T, F> Debug for FormatWith<T, F>
+where
+	F: Fn(&T, &mut core::fmt::Formatter<'_>) -> core::fmt::Result,
+{
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		(self.1)(&self.0, f)
+	}
+}
+
+/// Used internally for a list of key-value pairs in a context scope,
+/// e.g. `econtext_data!("loading", path = path.clone(), attempt = attempt)`.
+///
+/// Wraps a tuple of `(&'static str, T)` pairs (up to 12) and formats them as `path="foo.txt" attempt=3`.
+/// Create it with `KeyValues::new((("path", path), ("attempt", 3)))` to also run the key validators.
+pub struct KeyValues<T>(pub T);
+
+impl<T: KeyValuePairs> KeyValues<T> {
+	/// Wrap the pairs, checking the values in debug builds (see `add_key_validator`).
+	#[inline]
+	#[track_caller]
+	pub fn new(pairs: T) -> Self {
+		pairs.validate();
+		Self(pairs)
+	}
+}
+
+/// Tuples of `(&'static str, T)` pairs that can be wrapped by [`KeyValues::new`].
+#[doc(hidden)]
+pub trait KeyValuePairs {
+	fn validate(&self);
+}
+
+macro_rules! impl_debug_for_key_values {
+	($($index:tt $T:ident),+) => {
+		impl<$($T: Debug),+> Debug for KeyValues<($((&'static str, $T),)+)> {
+			#[allow(unused_assignments)]
+			fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+				let mut separator = "";
+				$(
+					let (key, value) = &(self.0).$index;
+					if is_redacted(key) {
+						write!(f, "{}{}=<redacted>", separator, key)?;
+					} else {
+						write!(f, "{}{}={:?}", separator, key, value)?;
+					}
+					separator = " ";
+				)+
+				Ok(())
+			}
+		}
+
+		impl<$($T: Debug),+> KeyValuePairs for ($((&'static str, $T),)+) {
+			#[track_caller]
+			fn validate(&self) {
+				#[cfg(feature = "std")]
+				if validate::any_key_validators() {
+					$(
+						let (key, value) = &self.$index;
+						validate::check_key(key, value);
+					)+
+				}
+			}
+		}
+	};
+}
+
+#[cfg(feature = "std")]
+use redact::is_redacted;
+
+#[cfg(not(feature = "std"))]
+fn is_redacted(_key: &str) -> bool {
+	false
+}
+
+impl_debug_for_key_values!(0 A);
+impl_debug_for_key_values!(0 A, 1 B);
+impl_debug_for_key_values!(0 A, 1 B, 2 C);
+impl_debug_for_key_values!(0 A, 1 B, 2 C, 3 D);
+impl_debug_for_key_values!(0 A, 1 B, 2 C, 3 D, 4 E);
+impl_debug_for_key_values!(0 A, 1 B, 2 C, 3 D, 4 E, 5 F);
+impl_debug_for_key_values!(0 A, 1 B, 2 C, 3 D, 4 E, 5 F, 6 G);
+impl_debug_for_key_values!(0 A, 1 B, 2 C, 3 D, 4 E, 5 F, 6 G, 7 H);
+impl_debug_for_key_values!(0 A, 1 B, 2 C, 3 D, 4 E, 5 F, 6 G, 7 H, 8 I);
+impl_debug_for_key_values!(0 A, 1 B, 2 C, 3 D, 4 E, 5 F, 6 G, 7 H, 8 I, 9 J);
+impl_debug_for_key_values!(0 A, 1 B, 2 C, 3 D, 4 E, 5 F, 6 G, 7 H, 8 I, 9 J, 10 K);
+impl_debug_for_key_values!(0 A, 1 B, 2 C, 3 D, 4 E, 5 F, 6 G, 7 H, 8 I, 9 J, 10 K, 11 L);
+
 // ----------------------------------------------------------------------------
 
-/// Prints all active error contexts to stderr.
+/// Prints all active error contexts to stderr, or to the sink set with [`set_output`].
 ///
 /// Example printout:
 ///
@@ -133,12 +768,9 @@ impl std::fmt::Debug for EmptyDebug {
 ///   example examples/example.rs:13: example::do_stuff
 ///   example examples/example.rs:20: main()
 /// ```
+#[cfg(feature = "std")]
 pub fn print_econtext() {
-	let context = econtext_string();
-	if !context.is_empty() {
-		eprintln!("ERROR CONTEXT:");
-		eprintln!("{}", context);
-	}
+	output::capture_and_output(true, &econtext_frames());
 }
 
 /// Returns the error context as a string.
@@ -149,32 +781,161 @@ pub fn print_econtext() {
 ///   example examples/example.rs:13: example::do_stuff
 ///   example examples/example.rs:20: main()
 /// ```
+///
+/// Repeated frames are collapsed and the number of frames is limited, see [`set_max_frames`].
 pub fn econtext_string() -> String {
-	ERROR_STACK.with(|value| unsafe {
-		if let Some(entry) = value.borrow().as_ref().and_then(|p| p.as_ref()) {
-			let mut output = String::new();
-			entry.write(&mut output);
-			output
+	stack::with_frames(|frames| report::stack_text(frames, Style::Plain))
+}
+
+/// A handle to the error context of the current thread, for dropping into log statements and debugger watch expressions.
+///
+/// ```
+/// econtext::econtext!("loading");
+/// econtext::econtext_data!("file", "a.txt".to_owned());
+/// assert_eq!(format!("{}", econtext::current()), r#"loading > file "a.txt""#);
+/// ```
+///
+/// `{}` (and `{:?}`) writes the message and data of each frame on one line, outermost first,
+/// and does not allocate (except in the `Debug` impls of the data).
+/// `{:#}` writes the same as [`econtext_string`].
+///
+/// The context is read when formatting, on the thread that formats it.
+pub fn current() -> CurrentContext {
+	CurrentContext { _private: () }
+}
+
+/// See [`current`].
+#[derive(Clone, Copy)]
+pub struct CurrentContext {
+	_private: (),
+}
+
+impl core::fmt::Display for CurrentContext {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		if f.alternate() {
+			write_econtext(f)
 		} else {
-			Default::default()
+			stack::write_messages(f)
 		}
-	})
+	}
+}
+
+impl core::fmt::Debug for CurrentContext {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		stack::write_messages(f)
+	}
+}
+
+/// Writes the error context, in the same format as [`econtext_string`].
+///
+/// Useful without the `std` feature, e.g. to send the context over a serial port from a `#[panic_handler]`.
+pub fn write_econtext(writer: &mut impl core::fmt::Write) -> core::fmt::Result {
+	stack::with_frames(|frames| report::write_stack(writer, frames, Style::Plain))
+}
+
+/// Returns the error context as a JSON array, innermost first.
+///
+/// ``` text
+/// [{"module":"example","file":"examples/example.rs","line":7,"message":"i","data":"4","kind":"data"}]
+/// ```
+///
+/// `data` is the `Debug` formatting of the data, or an empty string.
+/// Frames from [`econtext_boundary!`] also have `"boundary":true`.
+/// See also [`set_format`].
+pub fn econtext_json() -> String {
+	let mut json = String::new();
+	stack::with_frames(|frames| report::write_json_frames(&mut json, frames));
+	json
+}
+
+/// Returns all the active error contexts of the current thread, innermost first.
+pub fn econtext_frames() -> Vec<Frame> {
+	stack::with_frames(|frames| frames.iter().map(FrameRef::to_frame).collect())
 }
 
+/// Where to print the error context relative to the panic hook that was installed before ours.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "config", derive(serde::Deserialize), serde(rename_all = "snake_case"))]
+pub enum Position {
+	/// Print the error context first, then call the previous hook (e.g. the default panic message).
+	Before,
+
+	/// Call the previous hook first, then print the error context.
+	After,
+}
+
+#[cfg(feature = "std")]
+static PANIC_HOOK_INSTALLED: AtomicBool = AtomicBool::new(false);
+
 /// Call this once to add a panic hook that calls `print_econtext()`.
+///
+/// Same as `add_panic_hook_with(Position::Before)`.
+#[cfg(feature = "std")]
 pub fn add_panic_hook() {
+	add_panic_hook_with(Position::Before);
+}
+
+/// Call this once to add a panic hook that calls `print_econtext()`,
+/// either before or after the previously installed panic hook.
+///
+/// This lets you control the ordering relative to other crates that install panic hooks
+/// (e.g. `color-eyre` or `human-panic`), regardless of which one was installed first.
+///
+/// If other (semver-incompatible) versions of econtext are linked into the same binary,
+/// the context from all of them is printed by whichever hook runs first.
+///
+/// Calling this more than once has no effect. See also [`is_panic_hook_installed`].
+#[cfg(feature = "std")]
+pub fn add_panic_hook_with(position: Position) {
+	if PANIC_HOOK_INSTALLED.swap(true, Ordering::SeqCst) {
+		return;
+	}
+
 	let previous_hook = std::panic::take_hook();
 
-	std::panic::set_hook(Box::new(move |panic_info: &std::panic::PanicInfo| {
-		print_econtext();
-		previous_hook(panic_info);
+	let print = |panic_info: &std::panic::PanicHookInfo| {
+		// Other versions of econtext print our context too, so only the first hook to run should print:
+		let print = !peers::take_suppressed();
+		let frames = econtext_frames();
+		let report = output::capture_and_output(print, &frames);
+		if print {
+			peers::suppress_peers();
+		}
+		capture::on_panic(panic_info, &report);
+	};
+
+	std::panic::set_hook(Box::new(move |panic_info: &std::panic::PanicHookInfo| match position {
+		Position::Before => {
+			print(panic_info);
+			previous_hook(panic_info);
+		}
+		Position::After => {
+			previous_hook(panic_info);
+			print(panic_info);
+		}
 	}));
 }
 
+/// Returns `true` if [`add_panic_hook`] or [`add_panic_hook_with`] has been called.
+#[cfg(feature = "std")]
+pub fn is_panic_hook_installed() -> bool {
+	PANIC_HOOK_INSTALLED.load(Ordering::SeqCst)
+}
+
+/// Used by [`etodo!`] and [`eunimplemented!`] so that the context is printed even without the panic hook.
+#[doc(hidden)]
+pub fn __print_unless_panic_hook() {
+	#[cfg(feature = "std")]
+	if !is_panic_hook_installed() {
+		print_econtext();
+	}
+}
+
 // ----------------------------------------------------------------------------
 
 pub fn type_name_of<T>(_: T) -> &'static str {
-	std::any::type_name::<T>()
+	core::any::type_name::<T>()
 }
 
 #[macro_export]
@@ -187,18 +948,125 @@ macro_rules! current_function_name {
 		}};
 }
 
+/// Include the enabled cargo features of the calling crate in reports, to tell which feature combination was compiled.
+///
+/// This needs `econtext::build::emit_cargo_features()` in the `build.rs` of the crate, see [`build`].
+///
+/// ```ignore
+/// fn main() {
+/// 	econtext::register_cargo_features!();
+/// 	econtext::add_panic_hook();
+/// }
+/// ```
+///
+/// The report then ends with a line like `Cargo features of my_crate: default, gpu`.
+/// In [`Format::Json`] there is a `cargo_features` object with an array of features for each crate.
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! register_cargo_features {
+	() => {
+		$crate::add_cargo_features(env!("CARGO_PKG_NAME"), env!("ECONTEXT_CARGO_FEATURES"))
+	};
+}
+
+/// Include the last git commit of the file of each frame in JSON reports, so triage tools can find recent changes.
+///
+/// This needs the `blame` feature, and `econtext::build::emit_blame()` in the `build.rs` of the crate, see [`build`].
+///
+/// ```ignore
+/// fn main() {
+/// 	econtext::register_blame!();
+/// 	econtext::add_panic_hook();
+/// }
+/// ```
+///
+/// Each frame from the crate (judging by its module path) then has a `commit` field in [`Format::Json`] and [`econtext_json`].
+/// Frames from other crates, and frames without a module path (see [`api`]), don't.
+#[cfg(feature = "blame")]
+#[macro_export]
+macro_rules! register_blame {
+	() => {
+		$crate::add_blame(env!("CARGO_CRATE_NAME"), include!(concat!(env!("OUT_DIR"), "/econtext_blame.rs")))
+	};
+}
+
 // ----------------------------------------------------------------------------
 
+/// Used by the other macros to enter a scope until the end of the enclosing block.
+#[cfg(feature = "enabled")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __econtext_scope {
+	($kind:ident, boundary $message:expr, $data:expr) => {
+		let _scope = $crate::DataScope::new(module_path!(), file!(), line!(), $message, $data)
+			.kind($crate::FrameKind::$kind)
+			.boundary()
+			.push();
+	};
+	($kind:ident, $message:expr, $data:expr) => {
+		let _scope = $crate::DataScope::new(module_path!(), file!(), line!(), $message, $data)
+			.kind($crate::FrameKind::$kind)
+			.push();
+	};
+}
+
+/// Without the `enabled` feature the scopes compile to nothing,
+/// but the arguments are still type checked so that no warnings appear.
+#[cfg(not(feature = "enabled"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __econtext_scope {
+	($kind:ident, boundary $message:expr, $data:expr) => {
+		$crate::__econtext_scope!($kind, $message, $data);
+	};
+	($kind:ident, $message:expr, $data:expr) => {
+		if false {
+			let _ = ($message, $data);
+		}
+	};
+}
+
+/// Turns the data arguments of the other macros into an expression implementing `Debug`:
+/// `%value` (with `Display`), `?value` or `value` (with `Debug`), or `key = value, …` pairs (each with an optional sigil).
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __econtext_data {
+	(% $data:expr) => {
+		$crate::AsDisplay($data)
+	};
+	(? $data:expr) => {
+		$data
+	};
+	($key:ident = $($rest:tt)+) => {
+		$crate::__econtext_data!(@key_values [] $key = $($rest)+)
+	};
+	($data:expr) => {
+		$data
+	};
+
+	(@key_values [$($pairs:tt)*] $(,)?) => {
+		$crate::KeyValues::new(($($pairs)*))
+	};
+	(@key_values [$($pairs:tt)*] $key:ident = % $value:expr $(, $($rest:tt)*)?) => {
+		$crate::__econtext_data!(@key_values [$($pairs)* (stringify!($key), $crate::AsDisplay($value)),] $($($rest)*)?)
+	};
+	(@key_values [$($pairs:tt)*] $key:ident = ? $value:expr $(, $($rest:tt)*)?) => {
+		$crate::__econtext_data!(@key_values [$($pairs)* (stringify!($key), $value),] $($($rest)*)?)
+	};
+	(@key_values [$($pairs:tt)*] $key:ident = $value:expr $(, $($rest:tt)*)?) => {
+		$crate::__econtext_data!(@key_values [$($pairs)* (stringify!($key), $value),] $($($rest)*)?)
+	};
+}
+
 /// Provide a single `&'static str` message as context.
 ///
 /// Example: `econtext!("cleaning the floor");'
 ///
-/// This has a very low overhead of around 15 ns on a 2020 MacBook Pro.
+/// This has a very low overhead of around 15 ns with the default settings, see the [crate docs](crate) for what makes it slower.
 #[macro_export]
 macro_rules! econtext {
 	($message:expr) => {
-		let _scope = $crate::DataScope::new(module_path!(), file!(), line!(), $message, $crate::EmptyDebug {});
-		$crate::ERROR_STACK.with(|stack| *stack.borrow_mut() = Some(&_scope));
+		$crate::__econtext_scope!(Message, $message, $crate::EmptyDebug {});
 	};
 }
 
@@ -206,15 +1074,38 @@ macro_rules! econtext {
 ///
 /// Example: `econtext_data!("loop index", i);'
 ///
-/// This has a very low overhead of around 15 ns on a 2020 MacBook Pro.
+/// This has a very low overhead of around 15 ns with the default settings, see the [crate docs](crate) for what makes it slower.
+///
+/// You can also provide several named values, which are printed as `key=value` pairs:
+/// `econtext_data!("loading", path = path.clone(), attempt = attempt);'
+///
+/// The data is formatted with `Debug`. Prefix it with `%` to use `Display` instead (e.g. to print strings without quotes),
+/// both for single values and for named ones: `econtext_data!("loading", path = %path.display().to_string());'.
+/// Use [`format_with`] for custom formatting.
 ///
 /// Unfortunately `econtext_data!` does not support references, so things like &str must be converted into their owned versions,
 /// e.g. `econtext_data!("file_name", file_name.to_owned());'.
 #[macro_export]
 macro_rules! econtext_data {
-	($message:expr, $data:expr) => {
-		let _scope = $crate::DataScope::new(module_path!(), file!(), line!(), $message, $data);
-		$crate::ERROR_STACK.with(|stack| *stack.borrow_mut() = Some(&_scope));
+	($message:expr, $($data:tt)+) => {
+		$crate::__econtext_scope!(Data, $message, $crate::__econtext_data!($($data)+));
+	};
+}
+
+/// Like [`econtext!`] or [`econtext_data!`], but also marks the start of a new logical section of the stack,
+/// e.g. the handling of one request in a layered server.
+///
+/// Example: `econtext_boundary!("request", request_id);'
+///
+/// In the text report, a separator line is printed between the sections.
+/// In the JSON report and in [`Frame`], the frame has `boundary` set, so tools can split the stack into sections.
+#[macro_export]
+macro_rules! econtext_boundary {
+	($message:expr) => {
+		$crate::__econtext_scope!(Message, boundary $message, $crate::EmptyDebug {});
+	};
+	($message:expr, $($data:tt)+) => {
+		$crate::__econtext_scope!(Data, boundary $message, $crate::__econtext_data!($($data)+));
 	};
 }
 
@@ -222,18 +1113,11 @@ macro_rules! econtext_data {
 ///
 /// Example: `econtext_function!();'
 ///
-/// This has a very low overhead of around 15 ns on a 2020 MacBook Pro.
+/// This has a very low overhead of around 15 ns with the default settings, see the [crate docs](crate) for what makes it slower.
 #[macro_export]
 macro_rules! econtext_function {
 	() => {
-		let _scope = $crate::DataScope::new(
-			module_path!(),
-			file!(),
-			line!(),
-			$crate::current_function_name!(),
-			$crate::EmptyDebug {},
-			);
-		$crate::ERROR_STACK.with(|stack| *stack.borrow_mut() = Some(&_scope));
+		$crate::__econtext_scope!(Function, $crate::current_function_name!(), $crate::EmptyDebug {});
 	};
 }
 
@@ -241,20 +1125,71 @@ macro_rules! econtext_function {
 ///
 /// Example: `econtext_function_data!(function_argument);'
 ///
-/// This has a very low overhead of around 15 ns on a 2020 MacBook Pro.
+/// This has a very low overhead of around 15 ns with the default settings, see the [crate docs](crate) for what makes it slower.
+///
+/// You can also provide several named values, which are printed as `key=value` pairs:
+/// `econtext_function_data!(path = path.clone(), attempt = attempt);'
+///
+/// Like in [`econtext_data!`], prefix the data with `%` to format it with `Display` instead of `Debug`.
 ///
 /// Unfortunately `econtext_function_data!` does not support references, so things like &str must be converted into their owned versions,
 /// e.g. `econtext_function_data!("file_name", file_name.to_owned());'.
 #[macro_export]
 macro_rules! econtext_function_data {
-	($data:expr) => {
-		let _scope = $crate::DataScope::new(
-			module_path!(),
-			file!(),
-			line!(),
-			$crate::current_function_name!(),
-			$data,
-			);
-		$crate::ERROR_STACK.with(|stack| *stack.borrow_mut() = Some(&_scope));
+	($($data:tt)+) => {
+		$crate::__econtext_scope!(Function, $crate::current_function_name!(), $crate::__econtext_data!($($data)+));
+	};
+}
+
+/// Like `todo!()`, but first enters a scope with the message and data, so the report tells which path was hit.
+///
+/// Example: `etodo!("exporting to PDF", pages = pages.len())'
+///
+/// This panics with `not yet implemented: exporting to PDF`, and the innermost frame is `exporting to PDF pages=3`.
+/// The data is given like in [`econtext_data!`].
+/// If no panic hook is installed (see [`add_panic_hook`]), the context is printed before panicking.
+#[macro_export]
+macro_rules! etodo {
+	() => {
+		$crate::__econtext_panic!(todo)
+	};
+	($message:expr $(,)?) => {
+		$crate::__econtext_panic!(todo, $message, $crate::EmptyDebug {})
+	};
+	($message:expr, $($data:tt)+) => {
+		$crate::__econtext_panic!(todo, $message, $crate::__econtext_data!($($data)+))
+	};
+}
+
+/// Like `unimplemented!()`, but first enters a scope with the message and data. See [`etodo!`].
+///
+/// Example: `eunimplemented!("compression", method = method)'
+#[macro_export]
+macro_rules! eunimplemented {
+	() => {
+		$crate::__econtext_panic!(unimplemented)
+	};
+	($message:expr $(,)?) => {
+		$crate::__econtext_panic!(unimplemented, $message, $crate::EmptyDebug {})
 	};
+	($message:expr, $($data:tt)+) => {
+		$crate::__econtext_panic!(unimplemented, $message, $crate::__econtext_data!($($data)+))
+	};
+}
+
+/// Used by [`etodo!`] and [`eunimplemented!`].
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __econtext_panic {
+	($panic:ident) => {{
+		$crate::__econtext_scope!(Todo, $crate::current_function_name!(), $crate::EmptyDebug {});
+		$crate::__print_unless_panic_hook();
+		::core::$panic!()
+	}};
+	($panic:ident, $message:expr, $data:expr) => {{
+		let message: &'static str = $message;
+		$crate::__econtext_scope!(Todo, message, $data);
+		$crate::__print_unless_panic_hook();
+		::core::$panic!("{}", message)
+	}};
 }
@@ -0,0 +1,60 @@
+//! Emitting reports as `log` records, with the `log` feature.
+
+use crate::{Frame, Sink};
+
+/// A [`Sink`] that emits reports as `error!` records with the `econtext` target,
+/// so they end up wherever the rest of your logs go.
+///
+/// ```
+/// // Instead of stderr:
+/// econtext::set_output(econtext::LogSink::new());
+/// // Or in addition to it, with one record per frame:
+/// econtext::add_output(econtext::LogSink::per_frame(), econtext::Format::Text);
+/// # econtext::set_output(econtext::Stderr);
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LogSink {
+	per_frame: bool,
+}
+
+impl LogSink {
+	/// One record with the whole report, formatted according to the format given to
+	/// [`crate::add_output`] (e.g. [`crate::Format::SingleLine`] for line-based logs).
+	pub fn new() -> Self {
+		Self { per_frame: false }
+	}
+
+	/// One record per frame of the current thread, innermost first, with the file, line, and module of the frame.
+	///
+	/// The context of other threads and other versions of econtext is not included.
+	/// A report written without its frames (with [`Sink::write`]) is one record, as with [`Self::new`].
+	pub fn per_frame() -> Self {
+		Self { per_frame: true }
+	}
+}
+
+impl Sink for LogSink {
+	fn write(&self, report: &str) {
+		log::error!(target: "econtext", "{}", report.trim_end());
+	}
+
+	fn write_report(&self, report: &str, frames: &[Frame]) {
+		if !self.per_frame {
+			self.write(report);
+			return;
+		}
+
+		for frame in frames {
+			log::logger().log(
+				&log::Record::builder()
+					.level(log::Level::Error)
+					.target("econtext")
+					.module_path_static(Some(frame.module_path).filter(|module_path| !module_path.is_empty()))
+					.file_static(Some(frame.file))
+					.line(Some(frame.line))
+					.args(format_args!("{} {}", frame.message, frame.data))
+					.build(),
+			);
+		}
+	}
+}
@@ -0,0 +1,154 @@
+//! Thread info and timestamps for correlating reports with logs, and cargo features for triage.
+
+use std::{
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		OnceLock, RwLock,
+	},
+	thread::ThreadId,
+	time::{Duration, Instant, SystemTime},
+};
+
+static THREAD_INFO: AtomicBool = AtomicBool::new(false);
+static TIMESTAMPS: AtomicBool = AtomicBool::new(false);
+
+/// Global settings of econtext, see [`config`].
+#[derive(Clone, Copy, Debug)]
+pub struct GlobalConfig {
+	_private: (),
+}
+
+/// Change global settings of econtext. Each `with_` method takes effect right away.
+///
+/// ```
+/// econtext::config().with_thread_info(true).with_timestamps(true);
+/// ```
+pub fn config() -> GlobalConfig {
+	GlobalConfig { _private: () }
+}
+
+impl GlobalConfig {
+	/// Print the name and id of the thread in the header of each report (default: off).
+	///
+	/// ``` text
+	/// ERROR CONTEXT OF THREAD 'worker-3' (ThreadId(5)):
+	///   my_module src/main.rs:17: i 4
+	/// ```
+	///
+	/// In [`crate::Format::Json`] this adds a `thread` field.
+	pub fn with_thread_info(self, thread_info: bool) -> Self {
+		THREAD_INFO.store(thread_info, Ordering::Relaxed);
+		self
+	}
+
+	/// Record when each scope is entered, and print it (in UTC) before each frame (default: off).
+	///
+	/// ``` text
+	/// ERROR CONTEXT:
+	///   2026-10-16T09:41:07.123Z my_module src/main.rs:17: i 4
+	/// ```
+	///
+	/// In [`crate::Format::Json`] and [`crate::econtext_json`] this adds an `entered` field.
+	/// Scopes entered before this was turned on have no timestamp.
+	///
+	/// This reads a monotonic clock on every scope entry, which makes scopes about five times as slow.
+	/// There is no clock on `wasm32-unknown-unknown`, so there this does nothing.
+	pub fn with_timestamps(self, timestamps: bool) -> Self {
+		crate::stack::store_setting(&TIMESTAMPS, timestamps, true);
+		self
+	}
+}
+
+static CARGO_FEATURES: RwLock<Vec<(&'static str, Vec<&'static str>)>> = RwLock::new(Vec::new());
+
+/// Include the enabled cargo features of a crate in reports. Usually called with [`crate::register_cargo_features!`].
+///
+/// `features` is a comma-separated list.
+/// Calling this again for the same crate replaces its features.
+pub fn add_cargo_features(crate_name: &'static str, features: &'static str) {
+	let features = features.split(',').filter(|feature| !feature.is_empty()).collect();
+	let mut crates = CARGO_FEATURES.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+	crates.retain(|(name, _)| *name != crate_name);
+	crates.push((crate_name, features));
+}
+
+/// The crates registered with [`add_cargo_features`], and their features.
+pub(crate) fn cargo_features() -> Vec<(&'static str, Vec<&'static str>)> {
+	CARGO_FEATURES.read().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+}
+
+/// The crate name as in module paths (`-` replaced with `_`), and its `(file, commit)` pairs.
+#[cfg(feature = "blame")]
+type BlameTable = (String, &'static [(&'static str, &'static str)]);
+
+#[cfg(feature = "blame")]
+static BLAME: RwLock<Vec<BlameTable>> = RwLock::new(Vec::new());
+
+/// Include the last commit of the file of each frame in JSON reports. Usually called with [`crate::register_blame!`].
+///
+/// `commits` has a `(file, commit)` pair for each file of the crate `crate_name` (as in `CARGO_CRATE_NAME`), with paths relative to the crate directory.
+/// Only frames whose module path starts with the crate name get a commit:
+/// the one of the file with the same path, or else of the file that the path of the frame ends with.
+/// Calling this again for the same crate replaces its commits.
+#[cfg(feature = "blame")]
+pub fn add_blame(crate_name: &str, commits: &'static [(&'static str, &'static str)]) {
+	let crate_name = crate_name.replace('-', "_");
+	let mut tables = BLAME.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+	tables.retain(|(name, _)| *name != crate_name);
+	tables.push((crate_name, commits));
+}
+
+/// The last commit of `file` in the crate of `module_path`, from the tables given to [`add_blame`].
+#[cfg(feature = "blame")]
+pub(crate) fn commit_of(module_path: &str, file: &str) -> Option<&'static str> {
+	let crate_name = module_path.split("::").next().filter(|name| !name.is_empty())?;
+	let tables = BLAME.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+	let (_, commits) = tables.iter().find(|(name, _)| name == crate_name)?;
+	let is_suffix = |path: &str| file.strip_suffix(path).is_some_and(|rest| rest.ends_with('/') || rest.ends_with('\\'));
+	commits
+		.iter()
+		.find(|(path, _)| *path == file)
+		.or_else(|| commits.iter().find(|(path, _)| is_suffix(path)))
+		.map(|(_, commit)| *commit)
+}
+
+/// `"'name' (ThreadId(5))"`, or just the name (in quotes) or id if [`GlobalConfig::with_thread_info`] is off.
+pub(crate) fn thread_label(name: Option<&str>, id: ThreadId) -> String {
+	match (name, THREAD_INFO.load(Ordering::Relaxed)) {
+		(Some(name), true) => format!("'{}' ({:?})", name, id),
+		(Some(name), false) => format!("'{}'", name),
+		(None, _) => format!("{:?}", id),
+	}
+}
+
+/// The label of the current thread, if [`GlobalConfig::with_thread_info`] is on.
+pub(crate) fn current_thread_label() -> Option<String> {
+	THREAD_INFO.load(Ordering::Relaxed).then(|| {
+		let thread = std::thread::current();
+		thread_label(thread.name(), thread.id())
+	})
+}
+
+/// Microseconds since the unix epoch, or `0` if [`GlobalConfig::with_timestamps`] is off.
+#[inline]
+pub(crate) fn now_micros() -> u64 {
+	if cfg!(all(target_arch = "wasm32", target_os = "unknown")) {
+		return 0;
+	}
+	if TIMESTAMPS.load(Ordering::Relaxed) {
+		now_micros_slow()
+	} else {
+		0
+	}
+}
+
+/// `Instant::now()` is cheaper than `SystemTime::now()` on some platforms, and never goes backwards,
+/// so we read the wall clock once and offset it by the monotonic clock.
+fn now_micros_slow() -> u64 {
+	static START: OnceLock<(Instant, Duration)> = OnceLock::new();
+	let (start_instant, start_time) = START.get_or_init(|| {
+		let since_epoch = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+		(Instant::now(), since_epoch)
+	});
+	(*start_time + start_instant.elapsed()).as_micros().max(1) as u64
+}
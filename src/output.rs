@@ -0,0 +1,379 @@
+//! Where the error context is printed.
+
+use std::{
+	cell::RefCell,
+	path::PathBuf,
+	sync::{
+		atomic::{AtomicUsize, Ordering},
+		Arc, RwLock,
+	},
+	time::SystemTime,
+};
+
+use crate::{
+	report::{Format, Report, Section, Style},
+	Frame,
+};
+
+/// Something that error context reports can be written to, e.g. a log file or a GUI dialog.
+///
+/// Implemented for all `Fn(&str) + Send + Sync` closures.
+pub trait Sink: Send + Sync {
+	/// Write a full report, including the trailing newline,
+	/// or a section of it if [`Self::is_streaming`].
+	fn write(&self, report: &str);
+
+	/// Write a full report, made from `frames`: the context of the current thread, innermost first.
+	///
+	/// Calls [`Self::write`] by default. Override it to build the output from the frames (e.g. one log record per frame)
+	/// instead of reading the stack, which may have changed by then (e.g. behind a [`crate::BufferedSink`]).
+	/// Not called for the sections of a streamed report.
+	fn write_report(&self, report: &str, _frames: &[Frame]) {
+		self.write(report);
+	}
+
+	/// If `true`, [`Self::write`] is called once for each section of a [`Format::Text`] report
+	/// (the context of the current thread first, then other threads, …) as soon as it has been captured,
+	/// so that a crash while capturing the rest still leaves something useful.
+	///
+	/// Not used if [`crate::set_max_report_size`] has been called, since the whole report is needed for trimming.
+	/// Otherwise a streamed report is cut off at [`crate::DEFAULT_MAX_REPORT_SIZE`].
+	fn is_streaming(&self) -> bool {
+		false
+	}
+
+	/// Can this sink show ANSI colors? See [`crate::set_style`].
+	fn supports_color(&self) -> bool {
+		false
+	}
+}
+
+impl<F: Fn(&str) + Send + Sync> Sink for F {
+	fn write(&self, report: &str) {
+		self(report);
+	}
+}
+
+/// The default [`Sink`]: writes to stderr.
+///
+/// With the `wasm` feature, the default on `wasm32` is `Console` instead.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Stderr;
+
+impl Sink for Stderr {
+	fn write(&self, report: &str) {
+		eprint!("{}", report);
+	}
+
+	/// Stderr is unbuffered, so there is no reason to wait.
+	fn is_streaming(&self) -> bool {
+		true
+	}
+
+	/// If stderr is a terminal, and `NO_COLOR` is not set.
+	fn supports_color(&self) -> bool {
+		use std::io::IsTerminal as _;
+		std::env::var_os("NO_COLOR").is_none_or(|no_color| no_color.is_empty()) && std::io::stderr().is_terminal()
+	}
+}
+
+/// A [`Sink`] that writes each report to a new file in a directory, for collecting crash reports from the field.
+///
+/// The files are named `econtext-<unix time in ms>-<pid>-<n>.txt`.
+///
+/// ```no_run
+/// econtext::add_output(econtext::FileSink::new("crash_reports"), econtext::Format::Json);
+/// ```
+///
+/// With the `age` feature, the reports can be encrypted so that only the holder of the private key can read them,
+/// see [`FileSink::encrypted`].
+pub struct FileSink {
+	dir: PathBuf,
+
+	#[cfg(feature = "age")]
+	recipient: Option<age::x25519::Recipient>,
+}
+
+impl FileSink {
+	pub fn new(dir: impl Into<PathBuf>) -> Self {
+		Self {
+			dir: dir.into(),
+			#[cfg(feature = "age")]
+			recipient: None,
+		}
+	}
+
+	/// Encrypt the reports with an age X25519 public key (`age1…`), writing `.txt.age` files.
+	///
+	/// Decrypt them with e.g. `age --decrypt -i key.txt report.txt.age`.
+	#[cfg(feature = "age")]
+	pub fn encrypted(mut self, public_key: &str) -> Result<Self, &'static str> {
+		self.recipient = Some(public_key.parse()?);
+		Ok(self)
+	}
+
+	fn path(&self) -> PathBuf {
+		static NUMBER: AtomicUsize = AtomicUsize::new(0);
+		let millis = SystemTime::now()
+			.duration_since(SystemTime::UNIX_EPOCH)
+			.map_or(0, |duration| duration.as_millis());
+		#[cfg(feature = "age")]
+		let extension = if self.recipient.is_some() { "txt.age" } else { "txt" };
+		#[cfg(not(feature = "age"))]
+		let extension = "txt";
+		self.dir.join(format!(
+			"econtext-{}-{}-{}.{}",
+			millis,
+			std::process::id(),
+			NUMBER.fetch_add(1, Ordering::Relaxed),
+			extension
+		))
+	}
+
+	fn contents(&self, report: &str) -> std::io::Result<Vec<u8>> {
+		#[cfg(feature = "age")]
+		if let Some(recipient) = &self.recipient {
+			return encrypt(recipient, report.as_bytes());
+		}
+		Ok(report.as_bytes().to_vec())
+	}
+}
+
+#[cfg(feature = "age")]
+fn encrypt(recipient: &age::x25519::Recipient, plaintext: &[u8]) -> std::io::Result<Vec<u8>> {
+	use std::io::Write as _;
+
+	let encryptor = age::Encryptor::with_recipients(std::iter::once(recipient as &dyn age::Recipient))
+		.map_err(|err| std::io::Error::other(err.to_string()))?;
+	let mut encrypted = vec![];
+	let mut writer = encryptor.wrap_output(&mut encrypted)?;
+	writer.write_all(plaintext)?;
+	writer.finish()?;
+	Ok(encrypted)
+}
+
+impl Sink for FileSink {
+	fn write(&self, report: &str) {
+		let path = self.path();
+		let result = self
+			.contents(report)
+			.and_then(|contents| std::fs::create_dir_all(&self.dir).and_then(|_| std::fs::write(&path, contents)));
+		if let Err(err) = result {
+			eprintln!("econtext: failed to write {}: {}", path.display(), err);
+		}
+	}
+}
+
+struct Output {
+	sink: Arc<dyn Sink>,
+
+	/// `None` means the format set by [`crate::set_format`].
+	format: Option<Format>,
+}
+
+static OUTPUTS: RwLock<Vec<Output>> = RwLock::new(Vec::new());
+
+/// Set where [`crate::print_econtext`] (and thus the panic hook) writes the error context,
+/// replacing all previously added sinks.
+///
+/// The report is formatted according to [`crate::set_format`].
+/// The default is [`Stderr`] (or `Console` on `wasm32` with the `wasm` feature).
+///
+/// ```
+/// econtext::set_output(|report: &str| {
+/// 	// Send the report to a log file, a dialog, …
+/// });
+/// ```
+pub fn set_output(sink: impl Sink + 'static) {
+	let mut outputs = OUTPUTS.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+	outputs.clear();
+	outputs.push(Output {
+		sink: Arc::new(sink),
+		format: None,
+	});
+}
+
+/// Add another sink that the error context is written to, with its own format.
+///
+/// If you have not called [`set_output`], this replaces the default sink.
+///
+/// ```
+/// econtext::add_output(econtext::Stderr, econtext::Format::Text);
+/// econtext::add_output(|json: &str| { /* write to file */ }, econtext::Format::Json);
+/// ```
+pub fn add_output(sink: impl Sink + 'static, format: Format) {
+	let mut outputs = OUTPUTS.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+	outputs.push(Output {
+		sink: Arc::new(sink),
+		format: Some(format),
+	});
+}
+
+std::thread_local! {
+	static SINK_OVERRIDES: RefCell<Vec<Arc<dyn Sink>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Run `f`, writing all reports produced on this thread meanwhile (e.g. by the panic hook for caught panics)
+/// to `sink` instead of the sinks set by [`set_output`] and [`add_output`].
+///
+/// Useful for request-scoped logging in servers, and for capturing reports in tests:
+///
+/// ```
+/// use std::sync::{Arc, Mutex};
+///
+/// econtext::add_panic_hook();
+/// let reports = Arc::new(Mutex::new(Vec::new()));
+/// let sink = {
+/// 	let reports = reports.clone();
+/// 	move |report: &str| reports.lock().unwrap().push(report.to_owned())
+/// };
+/// let result = econtext::with_sink(sink, || {
+/// 	std::panic::catch_unwind(|| {
+/// 		econtext::econtext!("handling request");
+/// 		panic!("oops");
+/// 	})
+/// });
+/// assert!(result.is_err());
+/// assert!(reports.lock().unwrap()[0].contains("handling request"));
+/// ```
+///
+/// The report is formatted according to [`crate::set_format`].
+/// Calls can be nested, the innermost sink is used. Threads spawned by `f` use the normal sinks.
+pub fn with_sink<R>(sink: impl Sink + 'static, f: impl FnOnce() -> R) -> R {
+	struct PopOnDrop;
+
+	impl Drop for PopOnDrop {
+		fn drop(&mut self) {
+			let _ = SINK_OVERRIDES.try_with(|overrides| overrides.borrow_mut().pop());
+		}
+	}
+
+	SINK_OVERRIDES.with(|overrides| overrides.borrow_mut().push(Arc::new(sink)));
+	let _pop = PopOnDrop;
+	f()
+}
+
+/// The sink set by [`with_sink`] on this thread, if any.
+fn sink_override() -> Option<Arc<dyn Sink>> {
+	SINK_OVERRIDES
+		.try_with(|overrides| overrides.borrow().last().cloned())
+		.ok()
+		.flatten()
+}
+
+/// Like [`add_output`], but keeps the default sink if no other sink has been set.
+pub(crate) fn add_extra_output(sink: impl Sink + 'static, format: Format) {
+	let mut outputs = OUTPUTS.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+	if outputs.is_empty() {
+		outputs.push(Output {
+			sink: default_sink(),
+			format: None,
+		});
+	}
+	outputs.push(Output {
+		sink: Arc::new(sink),
+		format: Some(format),
+	});
+}
+
+/// Like [`set_output`] with the default sink.
+pub(crate) fn set_default_output() {
+	let mut outputs = OUTPUTS.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+	outputs.clear();
+	outputs.push(Output {
+		sink: default_sink(),
+		format: None,
+	});
+}
+
+fn default_sink() -> Arc<dyn Sink> {
+	#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+	return Arc::new(crate::wasm::Console);
+
+	#[cfg(not(all(feature = "wasm", target_arch = "wasm32")))]
+	Arc::new(Stderr)
+}
+
+/// Capture a report, and (if `print`) write it to all sinks:
+/// section by section to streaming sinks while capturing, then in full to the others.
+pub(crate) fn capture_and_output(print: bool, frames: &[Frame]) -> Report<'_> {
+	let outputs = if print { outputs() } else { vec![] };
+	let can_stream = crate::report::can_stream();
+	let (streaming, outputs): (Vec<_>, Vec<_>) = outputs
+		.into_iter()
+		.partition(|(sink, format, _)| can_stream && *format == Format::Text && sink.is_streaming());
+
+	let max_size = crate::report::max_report_size();
+	let mut written = vec![0; streaming.len()];
+	let report = Report::capture_streaming(frames, &mut |report, section| {
+		if matches!(section, Section::Footer) && written.iter().all(|&written| written == 0) {
+			return; // Like a full report, print nothing if there is no context
+		}
+		for ((sink, _, style), written) in streaming.iter().zip(&mut written) {
+			let text = report.section_text(section, *style);
+			if text.is_empty() || *written >= max_size {
+				continue;
+			}
+			if *written + text.len() <= max_size {
+				sink.write(&text);
+				*written += text.len();
+			} else {
+				// Cut off the rest of the report:
+				let cut_off = crate::report::cut_off_line(max_size);
+				let remaining = (max_size - *written).saturating_sub(cut_off.len());
+				if remaining > 0 {
+					sink.write(&crate::report::truncate_line(&text, remaining));
+				}
+				sink.write(&cut_off);
+				*written = max_size;
+			}
+		}
+	});
+
+	if !report.is_empty() {
+		output(&report, frames, &outputs);
+	}
+	report
+}
+
+/// All sinks, with their format and style.
+fn outputs() -> Vec<(Arc<dyn Sink>, Format, Style)> {
+	let mut outputs: Vec<(Arc<dyn Sink>, Format)> = if let Some(sink) = sink_override() {
+		vec![(sink, crate::report::format())]
+	} else {
+		OUTPUTS
+			.read()
+			.unwrap_or_else(|poisoned| poisoned.into_inner())
+			.iter()
+			.map(|output| (output.sink.clone(), output.format.unwrap_or_else(crate::report::format)))
+			.collect()
+	};
+	if outputs.is_empty() {
+		outputs.push((default_sink(), crate::report::format()));
+	}
+
+	let style = crate::report::style();
+	outputs
+		.into_iter()
+		.map(|(sink, format)| {
+			let style = if sink.supports_color() { style } else { style.without_color() };
+			(sink, format, style)
+		})
+		.collect()
+}
+
+/// Write the report to the sinks, formatting it once per format and style.
+fn output(report: &Report<'_>, frames: &[Frame], outputs: &[(Arc<dyn Sink>, Format, Style)]) {
+	let mut formatted: Vec<(Format, Style, String)> = vec![];
+	for (_, format, style) in outputs {
+		if !formatted.iter().any(|(f, s, _)| f == format && s == style) {
+			formatted.push((*format, *style, report.to_formatted_string(*format, *style)));
+		}
+	}
+
+	for (sink, format, style) in outputs {
+		if let Some((_, _, text)) = formatted.iter().find(|(f, s, _)| f == format && s == style) {
+			sink.write_report(text, frames);
+		}
+	}
+}
@@ -0,0 +1,96 @@
+//! Cooperation between semver-incompatible versions of econtext linked into the same binary.
+//!
+//! Each version has its own thread-local stack, so without this a panic hook installed by one version
+//! would not know about the scopes entered with another version.
+//!
+//! Each version registers itself in the shared registry of the `econtext_peers` crate,
+//! which all versions depend on, and which cargo unifies into a single copy.
+//!
+//! The first econtext panic hook to run collects the context of all peers,
+//! prints it together with its own, and tells the peers to not print anything for this panic.
+
+use std::{
+	cell::Cell,
+	sync::atomic::{AtomicBool, Ordering},
+};
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+fn render() -> String {
+	// A panic here would abort the process, since we are called from a panic hook:
+	std::panic::catch_unwind(crate::econtext_string).unwrap_or_default()
+}
+
+thread_local! {
+	static SUPPRESSED: Cell<bool> = const { Cell::new(false) };
+}
+
+fn suppress() {
+	if crate::is_panic_hook_installed() {
+		SUPPRESSED.with(|suppressed| suppressed.set(true));
+	}
+}
+
+/// Has a peer already printed our context for the current panic? Resets the flag.
+pub(crate) fn take_suppressed() -> bool {
+	SUPPRESSED.with(|suppressed| suppressed.replace(false))
+}
+
+// ----------------------------------------------------------------------------
+
+static REGISTERED: AtomicBool = AtomicBool::new(false);
+
+/// Make sure we are registered so that the panic hooks of other versions print our context.
+#[inline]
+pub(crate) fn ensure_registered() {
+	if !REGISTERED.load(Ordering::Relaxed) {
+		register();
+	}
+}
+
+#[cold]
+fn register() {
+	if REGISTERED.swap(true, Ordering::SeqCst) {
+		return;
+	}
+	econtext_peers::register(econtext_peers::Peer {
+		version: VERSION,
+		render,
+		suppress,
+	});
+	// Only the first push needs to check this:
+	crate::stack::add_slow_path_reason(false);
+}
+
+/// All other versions of econtext in this process.
+fn peers() -> impl Iterator<Item = econtext_peers::Peer> {
+	econtext_peers::peers().into_iter().filter(|peer| peer.version != VERSION)
+}
+
+/// The error context of the current thread as known by another version of econtext.
+pub(crate) struct PeerContext {
+	pub version: String,
+
+	/// One line per frame, as in `econtext_string()`.
+	pub context: String,
+}
+
+/// The error context of the current thread in all other versions of econtext.
+pub(crate) fn peer_contexts() -> Vec<PeerContext> {
+	peers()
+		.filter_map(|peer| {
+			let context = (peer.render)();
+			(!context.is_empty()).then_some(PeerContext {
+				version: peer.version.to_owned(),
+				context,
+			})
+		})
+		.collect()
+}
+
+/// Tell all other versions of econtext to not print anything for the current panic.
+pub(crate) fn suppress_peers() {
+	for peer in peers() {
+		(peer.suppress)();
+	}
+}
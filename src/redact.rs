@@ -0,0 +1,29 @@
+//! Keeping sensitive values out of reports.
+
+use std::sync::{
+	atomic::{AtomicBool, Ordering},
+	RwLock,
+};
+
+static ANY_REDACTED: AtomicBool = AtomicBool::new(false);
+static REDACTED_KEYS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+/// Replace the values of these keys with `<redacted>` in all reports.
+///
+/// This applies to named values, e.g. `econtext_data!("login", user = user, password = password)`
+/// prints `user="emil" password=<redacted>` after `set_redacted_keys(["password"])`.
+/// Keys are matched case-insensitively. Replaces the previous list.
+pub fn set_redacted_keys<S: Into<String>>(keys: impl IntoIterator<Item = S>) {
+	let keys: Vec<String> = keys.into_iter().map(|key| key.into().to_lowercase()).collect();
+	ANY_REDACTED.store(!keys.is_empty(), Ordering::Relaxed);
+	*REDACTED_KEYS.write().unwrap_or_else(|poisoned| poisoned.into_inner()) = keys;
+}
+
+pub(crate) fn is_redacted(key: &str) -> bool {
+	ANY_REDACTED.load(Ordering::Relaxed)
+		&& REDACTED_KEYS
+			.read()
+			.unwrap_or_else(|poisoned| poisoned.into_inner())
+			.iter()
+			.any(|redacted| redacted.eq_ignore_ascii_case(key))
+}
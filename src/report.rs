@@ -0,0 +1,1251 @@
+//! Turning the captured frames into a report.
+
+use alloc::{format, string::String, vec, vec::Vec};
+use core::{
+	fmt::{Debug, Write as _},
+	sync::atomic::{AtomicUsize, Ordering},
+	time::Duration,
+};
+
+use crate::FrameRef;
+#[cfg(feature = "std")]
+use crate::{peers::PeerContext, Frame};
+#[cfg(feature = "std")]
+use core::sync::atomic::{AtomicBool, AtomicU8};
+
+/// How the report is formatted when printed on panic.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "config", derive(serde::Deserialize), serde(rename_all = "snake_case"))]
+pub enum Format {
+	/// Human-readable text, one line per frame.
+	#[default]
+	Text,
+
+	/// All frames on a single line, separated by ` | `. Useful for line-based logs.
+	///
+	/// ``` text
+	/// ERROR CONTEXT: my_module src/main.rs:17: i 4 | my_module src/main.rs:5: While running
+	/// ```
+	SingleLine,
+
+	/// A single line of JSON, for log collectors.
+	///
+	/// ``` text
+	/// {"frames":[{"module":"my_module","file":"src/main.rs","line":17,"message":"i","data":"4","kind":"data"}],"other_threads":[]}
+	/// ```
+	///
+	/// Each frame is formatted like [`crate::econtext_json`].
+	/// With [`crate::GlobalConfig::with_thread_info`], there is also `thread`.
+	/// `other_threads` has a `thread` name and `frames` for each thread (see [`crate::set_print_all_threads`]).
+	/// If other versions of econtext are linked into the binary, there is also `peers`, with the `version`
+	/// and the human-readable `context` of each.
+	/// With [`crate::set_id_generator`], there are also `report_id` and `fingerprint`.
+	/// With [`crate::add_cargo_features`], there is also `cargo_features`.
+	/// With `register_blame!` (and the `blame` feature), frames have the last git `commit` of their file.
+	/// With [`crate::set_breadcrumbs`], there is also `recent`, with the recently exited frames, most recent first.
+	/// If the report was trimmed (see [`set_max_report_size`]) there is also an `omitted_frames` count.
+	Json,
+}
+
+#[cfg(feature = "std")]
+static FORMAT: AtomicU8 = AtomicU8::new(Format::Text as u8);
+
+/// Set the format of the report printed on panic. The default is [`Format::Text`].
+///
+/// This is used by sinks that don't specify their own format (see [`crate::add_output`]).
+#[cfg(feature = "std")]
+pub fn set_format(format: Format) {
+	FORMAT.store(format as u8, Ordering::Relaxed);
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn format() -> Format {
+	match FORMAT.load(Ordering::Relaxed) {
+		1 => Format::SingleLine,
+		2 => Format::Json,
+		_ => Format::Text,
+	}
+}
+
+/// The default of [`set_max_report_size`].
+///
+/// Streaming sinks like the default [`crate::Stderr`] are not trimmed to this, but cut off:
+/// the context of the current thread comes first (innermost frame first), so what is lost are
+/// its outermost frames and the sections after it. A `... report cut off ...` line marks the cut.
+/// Call [`set_max_report_size`] (even with this value) to trim them like the other sinks instead.
+#[cfg(feature = "std")]
+pub const DEFAULT_MAX_REPORT_SIZE: usize = 256 * 1024;
+
+#[cfg(feature = "std")]
+static MAX_REPORT_SIZE: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_REPORT_SIZE);
+
+#[cfg(feature = "std")]
+static MAX_REPORT_SIZE_SET: AtomicBool = AtomicBool::new(false);
+
+/// Limit the size of the report printed on panic to this many bytes (default: [`DEFAULT_MAX_REPORT_SIZE`]).
+///
+/// Useful when the report ends up in a log system that truncates long messages.
+/// If the report is too long, parts of it are dropped until it fits, least important first:
+/// 1. the recent context (see [`crate::set_breadcrumbs`]),
+/// 2. the context of other threads (see [`crate::set_print_all_threads`]), the last registered thread first,
+/// 3. the context from other versions of econtext linked into the binary,
+/// 4. frames from the middle of the stack, keeping the innermost frames (and the outermost one, if there is room).
+///
+/// Dropped sections and frames are replaced with a line saying how many were omitted
+/// (`omitted_frames` in [`Format::Json`]). The header, the footer and the innermost frame are always kept,
+/// the latter cut short if even it does not fit.
+///
+/// Until this is called, streaming sinks (see [`crate::Sink::is_streaming`]) get the report section by section,
+/// and are just cut off at the default limit (see [`DEFAULT_MAX_REPORT_SIZE`]). Use `usize::MAX` for no limit.
+#[cfg(feature = "std")]
+pub fn set_max_report_size(max_bytes: usize) {
+	MAX_REPORT_SIZE.store(max_bytes, Ordering::Relaxed);
+	MAX_REPORT_SIZE_SET.store(true, Ordering::Relaxed);
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn max_report_size() -> usize {
+	MAX_REPORT_SIZE.load(Ordering::Relaxed)
+}
+
+/// Trimming the report needs all of it, so streaming is only used if [`set_max_report_size`] has not been called
+/// (or was called with `usize::MAX`).
+#[cfg(feature = "std")]
+pub(crate) fn can_stream() -> bool {
+	!MAX_REPORT_SIZE_SET.load(Ordering::Relaxed) || max_report_size() == usize::MAX
+}
+
+/// The default of [`set_max_data_size`].
+pub const DEFAULT_MAX_DATA_SIZE: usize = 1024;
+
+static MAX_DATA_SIZE: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_DATA_SIZE);
+
+/// Cut off the formatted data of each frame after this many bytes, with a `…` (default: [`DEFAULT_MAX_DATA_SIZE`]).
+///
+/// Formatting stops there, so even data with a huge `Debug` output (e.g. a large `Vec`) is cheap to report.
+/// Use `usize::MAX` for no limit.
+pub fn set_max_data_size(max_bytes: usize) {
+	MAX_DATA_SIZE.store(max_bytes, Ordering::Relaxed);
+}
+
+/// Format `data` with `Debug`, limited to [`set_max_data_size`].
+pub(crate) fn format_data(data: &dyn Debug) -> String {
+	/// Fails once full, which stops the `Debug` impl.
+	struct Limited {
+		text: String,
+		max_len: usize,
+		truncated: bool,
+	}
+
+	impl core::fmt::Write for Limited {
+		fn write_str(&mut self, s: &str) -> core::fmt::Result {
+			let remaining = self.max_len - self.text.len();
+			if s.len() <= remaining {
+				self.text.push_str(s);
+				return Ok(());
+			}
+			let mut end = remaining;
+			while !s.is_char_boundary(end) {
+				end -= 1;
+			}
+			self.text.push_str(&s[..end]);
+			self.truncated = true;
+			Err(core::fmt::Error)
+		}
+	}
+
+	let mut limited = Limited {
+		text: String::new(),
+		max_len: MAX_DATA_SIZE.load(Ordering::Relaxed),
+		truncated: false,
+	};
+	// An error means we stopped it, or that the `Debug` impl failed, in which case we keep what it wrote:
+	write!(limited, "{:?}", data).ok();
+	if limited.truncated {
+		limited.text.push('…');
+	}
+	limited.text
+}
+
+/// How the human-readable reports ([`Format::Text`] and [`Format::SingleLine`]) look. See [`set_style`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "config", derive(serde::Deserialize), serde(rename_all = "snake_case"))]
+pub enum Style {
+	/// `module file:line: message data`
+	#[default]
+	Plain,
+
+	/// Like [`Self::Plain`], with ANSI colors: the location dimmed, the message bold, and the data highlighted.
+	Color,
+
+	/// Without the module, and with only the last two components of the file path: `src/main.rs:17: message data`
+	Compact,
+
+	/// [`Self::Compact`] with the colors of [`Self::Color`].
+	CompactColor,
+}
+
+impl Style {
+	pub fn is_color(self) -> bool {
+		matches!(self, Self::Color | Self::CompactColor)
+	}
+
+	pub fn is_compact(self) -> bool {
+		matches!(self, Self::Compact | Self::CompactColor)
+	}
+
+	/// The same style without colors.
+	pub fn without_color(self) -> Self {
+		match self {
+			Self::Color => Self::Plain,
+			Self::CompactColor => Self::Compact,
+			style => style,
+		}
+	}
+
+	#[cfg(feature = "std")]
+	fn parse(name: &str) -> Option<Self> {
+		match name.trim().to_ascii_lowercase().as_str() {
+			"plain" => Some(Self::Plain),
+			"color" => Some(Self::Color),
+			"compact" => Some(Self::Compact),
+			"compact_color" => Some(Self::CompactColor),
+			_ => None,
+		}
+	}
+}
+
+#[cfg(feature = "std")]
+static STYLE: AtomicU8 = AtomicU8::new(Style::Plain as u8);
+
+/// Set how the human-readable reports look. The default is [`Style::Plain`].
+///
+/// ```
+/// econtext::set_style(econtext::Style::Color);
+/// # econtext::set_style(econtext::Style::Plain);
+/// ```
+///
+/// The environment variable `ECONTEXT_STYLE` (`plain`, `color`, `compact`, or `compact_color`) overrides this,
+/// so users can change it without recompiling.
+///
+/// Colors are only used for sinks that support them, e.g. [`crate::Stderr`] when it is a terminal
+/// and the `NO_COLOR` environment variable is not set. Other sinks get the same style without colors.
+#[cfg(feature = "std")]
+pub fn set_style(style: Style) {
+	STYLE.store(style as u8, Ordering::Relaxed);
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn style() -> Style {
+	if let Some(style) = std::env::var("ECONTEXT_STYLE").ok().as_deref().and_then(Style::parse) {
+		return style;
+	}
+	match STYLE.load(Ordering::Relaxed) {
+		1 => Style::Color,
+		2 => Style::Compact,
+		3 => Style::CompactColor,
+		_ => Style::Plain,
+	}
+}
+
+static MAX_FRAMES: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+/// Print at most this many frames per thread (default: no limit).
+///
+/// If there are more, the innermost and outermost frames are printed
+/// with a `... N frames omitted ...` marker in between.
+///
+/// This is applied after collapsing repeated frames (e.g. from recursion),
+/// which are always printed once followed by `... repeated N more times ...`.
+pub fn set_max_frames(max_frames: usize) {
+	MAX_FRAMES.store(max_frames, Ordering::Relaxed);
+}
+
+fn max_frames() -> usize {
+	MAX_FRAMES.load(Ordering::Relaxed)
+}
+
+// ----------------------------------------------------------------------------
+
+/// One line in the report of a stack.
+pub(crate) enum Line<'r, 'a> {
+	Frame(&'r FrameRef<'a>),
+
+	/// The frame before was repeated this many more times.
+	Repeated(usize),
+
+	/// This many frames were left out.
+	Omitted(usize),
+
+	/// Between the sections of the stack, after a frame with [`FrameRef::boundary`].
+	Boundary,
+}
+
+impl Line<'_, '_> {
+	/// How many frames of the stack this line represents.
+	fn num_frames(&self) -> usize {
+		match self {
+			Line::Frame(_) => 1,
+			Line::Repeated(count) | Line::Omitted(count) => *count,
+			Line::Boundary => 0,
+		}
+	}
+
+	fn write(&self, writer: &mut dyn core::fmt::Write, style: Style) -> core::fmt::Result {
+		match self {
+			Line::Frame(frame) => {
+				writer.write_str("  ")?;
+				write_frame(writer, frame, style)?;
+				writer.write_char('\n')
+			}
+			Line::Repeated(count) => writeln!(writer, "  ... repeated {} more times ...", count),
+			Line::Omitted(count) => writer.write_str(&omitted_frames_line(*count)),
+			Line::Boundary => writer.write_str("  --------\n"),
+		}
+	}
+
+	#[cfg(feature = "std")]
+	fn to_text(&self, style: Style) -> String {
+		let mut text = String::new();
+		self.write(&mut text, style).ok();
+		text
+	}
+}
+
+/// Collapse runs of repeated frames, and limit the number of frames to [`set_max_frames`].
+pub(crate) fn lines<'r, 'a>(frames: &'r [FrameRef<'a>]) -> Vec<Line<'r, 'a>> {
+	// A frame, maybe followed by a `Line::Repeated`:
+	let mut groups: Vec<Vec<Line<'r, 'a>>> = vec![];
+	let mut i = 0;
+	while i < frames.len() {
+		let frame = &frames[i];
+		let run = frames[i..].iter().take_while(|other| other.is_same_site(frame)).count();
+		if run >= 3 {
+			groups.push(vec![Line::Frame(frame), Line::Repeated(run - 1)]);
+			i += run;
+		} else {
+			groups.push(vec![Line::Frame(frame)]);
+			i += 1;
+		}
+	}
+
+	let max_frames = max_frames();
+	if groups.len() > max_frames {
+		let num_innermost = max_frames.div_ceil(2);
+		let num_outermost = max_frames / 2;
+		let outermost = groups.split_off(groups.len() - num_outermost);
+		let omitted: usize = groups
+			.drain(num_innermost..)
+			.flatten()
+			.map(|line| line.num_frames())
+			.sum();
+		groups.push(vec![Line::Omitted(omitted)]);
+		groups.extend(outermost);
+	}
+
+	let num_groups = groups.len();
+	for group in &mut groups[..num_groups.saturating_sub(1)] {
+		if matches!(group[0], Line::Frame(frame) if frame.boundary) {
+			group.push(Line::Boundary);
+		}
+	}
+
+	groups.into_iter().flatten().collect()
+}
+
+/// One line per frame, like [`crate::econtext_string`].
+pub(crate) fn stack_text(frames: &[FrameRef<'_>], style: Style) -> String {
+	let mut text = String::new();
+	write_stack(&mut text, frames, style).ok();
+	text
+}
+
+pub(crate) fn write_stack(writer: &mut dyn core::fmt::Write, frames: &[FrameRef<'_>], style: Style) -> core::fmt::Result {
+	lines(frames).iter().try_for_each(|line| line.write(writer, style))
+}
+
+/// Like the `Display` of [`FrameRef`], in the given style.
+fn write_frame(writer: &mut dyn core::fmt::Write, frame: &FrameRef<'_>, style: Style) -> core::fmt::Result {
+	if style == Style::Plain {
+		return write!(writer, "{}", frame);
+	}
+
+	let (dim, bold, highlight, reset) = if style.is_color() {
+		("\x1b[2m", "\x1b[1m", "\x1b[36m", "\x1b[0m")
+	} else {
+		("", "", "", "")
+	};
+	writer.write_str(dim)?;
+	if let Some(entered) = frame.entered {
+		write_timestamp(writer, entered)?;
+		writer.write_char(' ')?;
+	}
+	if !style.is_compact() && !frame.module_path.is_empty() {
+		write!(writer, "{} ", frame.module_path)?;
+	}
+	let file = if style.is_compact() { short_path(frame.file) } else { frame.file };
+	write!(writer, "{}:{}:{} {}{}{}", file, frame.line, reset, bold, frame.message, reset)?;
+	let data = frame.data();
+	if !data.is_empty() {
+		write!(writer, " {}{}{}", highlight, data, reset)?;
+	}
+	Ok(())
+}
+
+/// The last two components of a path, e.g. `src/main.rs`.
+fn short_path(path: &str) -> &str {
+	let mut separators = path.rmatch_indices(['/', '\\']).map(|(i, _)| i);
+	match (separators.next(), separators.next()) {
+		(Some(_), Some(i)) => &path[i + 1..],
+		_ => path,
+	}
+}
+
+// ----------------------------------------------------------------------------
+
+/// The error context of another thread.
+#[cfg(feature = "std")]
+pub(crate) struct ThreadContext {
+	/// The thread name in quotes, or its id.
+	pub name: String,
+
+	/// Innermost first.
+	pub frames: Vec<Frame>,
+}
+
+#[cfg(feature = "std")]
+impl ThreadContext {
+	pub(crate) fn frame_refs(&self) -> Vec<FrameRef<'_>> {
+		self.frames.iter().map(FrameRef::from).collect()
+	}
+}
+
+/// A part of the text report, in the order they are printed. See [`Report::capture_streaming`].
+#[cfg(feature = "std")]
+#[derive(Clone, Copy)]
+pub(crate) enum Section {
+	/// The header and the frames of the current thread.
+	Frames,
+
+	/// An index into [`Report::peers`].
+	Peer(usize),
+
+	/// An index into [`Report::other_threads`].
+	Thread(usize),
+
+	Recent,
+
+	/// The cargo features and ids.
+	Footer,
+}
+
+/// Everything we print on panic.
+///
+/// Borrows from the stack of the current thread, so it must not outlive the scopes on it.
+#[cfg(feature = "std")]
+#[derive(Default)]
+pub(crate) struct Report<'a> {
+	/// The context of the current thread, innermost first.
+	pub frames: Vec<FrameRef<'a>>,
+
+	/// The context of the current thread in other versions of econtext in the same binary.
+	pub peers: Vec<PeerContext>,
+
+	/// See [`crate::set_print_all_threads`].
+	pub other_threads: Vec<ThreadContext>,
+
+	/// Recently exited scopes of the current thread, most recent first. See [`crate::set_breadcrumbs`].
+	pub recent: Vec<Frame>,
+
+	/// The name and id of the current thread. See [`crate::GlobalConfig::with_thread_info`].
+	pub thread: Option<String>,
+
+	/// See [`crate::add_cargo_features`].
+	pub cargo_features: Vec<(&'static str, Vec<&'static str>)>,
+
+	/// The report id and fingerprint. See [`crate::set_id_generator`].
+	pub ids: Option<(String, String)>,
+}
+
+#[cfg(feature = "std")]
+impl<'a> Report<'a> {
+	/// Capture everything, with the given `frames` of the current thread, calling `on_section` as soon as each section has been captured,
+	/// so that the first sections can be written before we do anything risky,
+	/// like formatting the data of other threads.
+	pub(crate) fn capture_streaming(frames: &'a [Frame], on_section: &mut dyn FnMut(&Self, Section)) -> Self {
+		if crate::is_disabled_for_current_thread() {
+			return Self::default(); // Don't show anything to untrusted code
+		}
+
+		let frames: Vec<FrameRef<'a>> = frames.iter().map(FrameRef::from).collect();
+		let mut report = Self {
+			ids: crate::ids::report_ids(&frames),
+			frames,
+			thread: crate::metadata::current_thread_label(),
+			cargo_features: crate::metadata::cargo_features(),
+			..Self::default()
+		};
+		on_section(&report, Section::Frames);
+
+		for peer in crate::peers::peer_contexts() {
+			report.peers.push(peer);
+			on_section(&report, Section::Peer(report.peers.len() - 1));
+		}
+
+		if crate::threads::print_all_threads() {
+			crate::threads::for_each_other_thread(|thread| {
+				report.other_threads.push(thread);
+				on_section(&report, Section::Thread(report.other_threads.len() - 1));
+			});
+		}
+
+		report.recent = crate::breadcrumbs::recent();
+		on_section(&report, Section::Recent);
+
+		on_section(&report, Section::Footer);
+		report
+	}
+
+	/// One section of the text report (empty if there is nothing in it), without any trimming.
+	pub fn section_text(&self, section: Section, style: Style) -> String {
+		match section {
+			Section::Frames => {
+				let lines: Vec<(String, usize)> = lines(&self.frames).iter().map(|line| (line.to_text(style), 0)).collect();
+				render(&self.header(), &lines, &[], 0)
+			}
+			Section::Peer(index) => peer_text(&self.peers[index]),
+			Section::Thread(index) => thread_text(&self.other_threads[index], style),
+			Section::Recent => self.recent_text(style).unwrap_or_default(),
+			Section::Footer => self.footer(),
+		}
+	}
+
+	fn header(&self) -> String {
+		match &self.thread {
+			Some(thread) => format!("ERROR CONTEXT OF THREAD {}:\n", thread),
+			None => "ERROR CONTEXT:\n".to_owned(),
+		}
+	}
+
+	fn recent_text(&self, style: Style) -> Option<String> {
+		// Not collapsed, since the data is what tells the scopes apart:
+		(!self.recent.is_empty()).then(|| {
+			let mut lines = String::new();
+			for frame in &self.recent {
+				lines += "  ";
+				write_frame(&mut lines, &frame.into(), style).ok();
+				lines += "\n";
+			}
+			format!("RECENT CONTEXT (most recent first):\n{}\n", lines)
+		})
+	}
+
+	fn footer(&self) -> String {
+		let mut footer = String::new();
+		for (crate_name, features) in &self.cargo_features {
+			footer += &format!("Cargo features of {}: {}\n", crate_name, features.join(", "));
+		}
+		if let Some((report_id, fingerprint)) = &self.ids {
+			footer += &format!("Report id: {}, fingerprint: {}\n", report_id, fingerprint);
+		}
+		footer
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.frames.is_empty() && self.peers.is_empty() && self.other_threads.is_empty() && self.recent.is_empty()
+	}
+
+	pub fn to_formatted_string(&self, format: Format, style: Style) -> String {
+		match format {
+			Format::Text => self.to_text(style),
+			Format::SingleLine => self.to_single_line(style),
+			Format::Json => self.to_json(),
+		}
+	}
+
+	/// All frames on one line, trimmed to [`set_max_report_size`].
+	///
+	/// Frames are separated by ` | ` and sections by ` || `. Newlines in the messages and data are escaped as `\n`.
+	pub fn to_single_line(&self, style: Style) -> String {
+		self.single_line_within(style, max_report_size())
+	}
+
+	fn single_line_within(&self, style: Style, max_size: usize) -> String {
+		let mut items = single_line_items(&self.frames, style);
+		let peers = self.peers.iter().map(|peer| {
+			let items: Vec<(String, usize)> = peer.context.lines().map(|line| (escape_newlines(line.trim()), 1)).collect();
+			single_line_section(&format!("ERROR CONTEXT (econtext {}):", peer.version), &items)
+		});
+		let threads = self.other_threads.iter().map(|thread| {
+			let items = single_line_items(&thread.frame_refs(), style);
+			single_line_section(&format!("ERROR CONTEXT OF THREAD {}:", thread.name), &items)
+		});
+		let recent = (!self.recent.is_empty()).then(|| {
+			let items: Vec<(String, usize)> = self
+				.recent
+				.iter()
+				.map(|frame| {
+					let mut text = String::new();
+					write_frame(&mut text, &frame.into(), style).ok();
+					(escape_newlines(&text), 1)
+				})
+				.collect();
+			single_line_section("RECENT CONTEXT (most recent first):", &items)
+		});
+		let mut sections: Vec<String> = peers.chain(threads).chain(recent).collect();
+		let footer = self.footer();
+		let header = self.header();
+		let render = |items: &[(String, usize)], sections: &[String], omitted_sections: usize| {
+			let mut parts = vec![];
+			if !items.is_empty() {
+				parts.push(single_line_section(header.trim_end(), items));
+			}
+			parts.extend(sections.iter().cloned());
+			if omitted_sections > 0 {
+				parts.push(format!("({} more section(s) omitted)", omitted_sections));
+			}
+			parts.extend(footer.lines().map(ToOwned::to_owned));
+			parts.join(" || ") + "\n"
+		};
+
+		let mut omitted_sections = 0;
+		let line = render(&items, &sections, omitted_sections);
+		if line.len() <= max_size {
+			return line;
+		}
+
+		// Same priorities as for the text report:
+		while !sections.is_empty() {
+			sections.pop();
+			omitted_sections += 1;
+			let line = render(&items, &sections, omitted_sections);
+			if line.len() <= max_size {
+				return line;
+			}
+		}
+
+		// `trim_lines` works on indented lines, which take as many bytes as the items with their ` | ` separators:
+		let num_frames = items.iter().map(|(_, num_frames)| num_frames).sum();
+		let overhead = render(&[], &[], omitted_sections).len() + header.len() + omitted_frames_line(num_frames).len();
+		let lines = items.into_iter().map(|(item, num_frames)| (format!("  {}\n", item), num_frames)).collect();
+		items = trim_lines(lines, max_size.saturating_sub(overhead))
+			.into_iter()
+			.map(|(line, num_frames)| {
+				let item = line.strip_prefix("  ").unwrap_or(&line);
+				(item.strip_suffix('\n').unwrap_or(item).to_owned(), num_frames)
+			})
+			.collect();
+		render(&items, &sections, omitted_sections)
+	}
+
+	/// The human-readable report, trimmed to [`set_max_report_size`].
+	pub fn to_text(&self, style: Style) -> String {
+		self.text_within(style, max_report_size())
+	}
+
+	fn text_within(&self, style: Style, max_size: usize) -> String {
+		let mut lines: Vec<(String, usize)> = lines(&self.frames)
+			.iter()
+			.map(|line| (line.to_text(style), line.num_frames()))
+			.collect();
+		let peers = self.peers.iter().map(peer_text);
+		let threads = self.other_threads.iter().map(|thread| thread_text(thread, style));
+		let mut sections: Vec<String> = peers.chain(threads).chain(self.recent_text(style)).collect();
+		let footer = self.footer();
+		let header = self.header();
+		let render = |lines: &[(String, usize)], sections: &[String], omitted_sections: usize| {
+			render(&header, lines, sections, omitted_sections) + &footer
+		};
+
+		let mut omitted_sections = 0;
+		let text = render(&lines, &sections, omitted_sections);
+		if text.len() <= max_size {
+			return text;
+		}
+
+		// Least important first: the recent context, the other threads, then other versions of econtext.
+		while !sections.is_empty() {
+			sections.pop();
+			omitted_sections += 1;
+			let text = render(&lines, &sections, omitted_sections);
+			if text.len() <= max_size {
+				return text;
+			}
+		}
+
+		// Then the middle of the stack:
+		let num_frames = lines.iter().map(|(_, num_frames)| num_frames).sum();
+		let overhead = render(&[], &[], omitted_sections).len() + header.len() + omitted_frames_line(num_frames).len();
+		lines = trim_lines(lines, max_size.saturating_sub(overhead));
+		render(&lines, &sections, omitted_sections)
+	}
+
+	/// The report as a single line of JSON, trimmed to [`set_max_report_size`].
+	pub fn to_json(&self) -> String {
+		self.json_within(max_report_size())
+	}
+
+	fn json_within(&self, max_size: usize) -> String {
+		let (mut frames, mut omitted_frames) = json_frames(&lines(&self.frames));
+		let mut threads: Vec<String> = self.other_threads.iter().map(thread_json).collect();
+		let mut peers: Vec<String> = self.peers.iter().map(peer_json).collect();
+		let mut recent = (!self.recent.is_empty()).then(|| {
+			let mut recent = String::new();
+			write_json_frames(&mut recent, &self.recent.iter().map(FrameRef::from).collect::<Vec<_>>());
+			recent
+		});
+
+		// Everything is formatted once above, so deciding what to drop only needs the sizes:
+		let fixed_size = self.render_json(&[], &[], &[], None, 0).len();
+		let sections_size = |threads: &[String], peers: &[String], recent: Option<&String>| {
+			let peers_size = if peers.is_empty() { 0 } else { ",\"peers\":[]".len() + json_list_size(peers) };
+			let recent_size = recent.map_or(0, |recent| ",\"recent\":".len() + recent.len());
+			json_list_size(threads) + peers_size + recent_size
+		};
+		let size = |frames: &[(String, usize)], threads: &[String], peers: &[String], recent: Option<&String>, omitted_frames: usize| {
+			let frames: Vec<&String> = frames.iter().map(|(json, _)| json).collect();
+			fixed_size + json_list_size(&frames) + sections_size(threads, peers, recent) + omitted_frames_size(omitted_frames)
+		};
+
+		// Same priorities as for the text report:
+		if size(&frames, &threads, &peers, recent.as_ref(), omitted_frames) > max_size {
+			recent = None;
+		}
+		while !threads.is_empty() && size(&frames, &threads, &peers, recent.as_ref(), omitted_frames) > max_size {
+			threads.pop();
+		}
+		while !peers.is_empty() && size(&frames, &threads, &peers, recent.as_ref(), omitted_frames) > max_size {
+			peers.pop();
+		}
+		if size(&frames, &threads, &peers, recent.as_ref(), omitted_frames) > max_size {
+			let num_frames = frames.iter().map(|(_, num_frames)| num_frames).sum::<usize>() + omitted_frames;
+			let overhead = fixed_size + sections_size(&threads, &peers, recent.as_ref()) + omitted_frames_size(num_frames);
+			let num_kept;
+			(frames, num_kept) = trim_json_frames(frames, max_size.saturating_sub(overhead));
+			omitted_frames = num_frames - num_kept;
+		}
+
+		self.render_json(&frames, &threads, &peers, recent.as_deref(), omitted_frames)
+	}
+
+	fn render_json(&self, frames: &[(String, usize)], threads: &[String], peers: &[String], recent: Option<&str>, omitted_frames: usize) -> String {
+		let mut json = String::from("{");
+		if let Some(thread) = &self.thread {
+			json += "\"thread\":";
+			write_json_string(&mut json, thread);
+			json += ",";
+		}
+		json += &format!("\"frames\":[{}]", join_frames(frames));
+		json += &format!(",\"other_threads\":[{}]", threads.join(","));
+		if !peers.is_empty() {
+			json += &format!(",\"peers\":[{}]", peers.join(","));
+		}
+		if let Some(recent) = recent {
+			json += ",\"recent\":";
+			json += recent;
+		}
+		if let Some((report_id, fingerprint)) = &self.ids {
+			json += ",\"report_id\":";
+			write_json_string(&mut json, report_id);
+			json += ",\"fingerprint\":";
+			write_json_string(&mut json, fingerprint);
+		}
+		if !self.cargo_features.is_empty() {
+			json += ",\"cargo_features\":{";
+			for (i, (crate_name, features)) in self.cargo_features.iter().enumerate() {
+				if i > 0 {
+					json += ",";
+				}
+				write_json_string(&mut json, crate_name);
+				json += ":[";
+				for (j, feature) in features.iter().enumerate() {
+					if j > 0 {
+						json += ",";
+					}
+					write_json_string(&mut json, feature);
+				}
+				json += "]";
+			}
+			json += "}";
+		}
+		if omitted_frames > 0 {
+			json += &format!(",\"omitted_frames\":{}", omitted_frames);
+		}
+		json += "}\n";
+		json
+	}
+}
+
+/// The lines of [`lines`] for [`Report::to_single_line`], with the number of frames each represents.
+#[cfg(feature = "std")]
+fn single_line_items(frames: &[FrameRef<'_>], style: Style) -> Vec<(String, usize)> {
+	lines(frames)
+		.iter()
+		.map(|line| {
+			let mut text = String::new();
+			line.write(&mut text, style).ok();
+			(escape_newlines(text.trim()), line.num_frames())
+		})
+		.collect()
+}
+
+/// `header item | item | …`
+#[cfg(feature = "std")]
+fn single_line_section(header: &str, items: &[(String, usize)]) -> String {
+	let items: Vec<&str> = items.iter().map(|(item, _)| item.as_str()).collect();
+	format!("{} {}", header, items.join(" | "))
+}
+
+#[cfg(feature = "std")]
+fn escape_newlines(text: &str) -> String {
+	text.replace('\r', "\\r").replace('\n', "\\n")
+}
+
+#[cfg(feature = "std")]
+fn peer_text(peer: &PeerContext) -> String {
+	format!("ERROR CONTEXT (econtext {}):\n{}\n", peer.version, peer.context)
+}
+
+#[cfg(feature = "std")]
+fn thread_text(thread: &ThreadContext, style: Style) -> String {
+	format!("ERROR CONTEXT OF THREAD {}:\n{}\n", thread.name, stack_text(&thread.frame_refs(), style))
+}
+
+/// JSON objects for the frames (and how many frames each represents), and the number of omitted frames.
+#[cfg(feature = "std")]
+fn json_frames(lines: &[Line<'_, '_>]) -> (Vec<(String, usize)>, usize) {
+	let mut frames: Vec<(String, usize)> = vec![];
+	let mut omitted = 0;
+	for line in lines {
+		match line {
+			Line::Frame(frame) => {
+				let mut json = String::new();
+				write_json_frame(&mut json, frame);
+				frames.push((json, 1));
+			}
+			Line::Repeated(count) => {
+				if let Some((json, num_frames)) = frames.last_mut() {
+					json.pop(); // '}'
+					*json += &format!(",\"repeated\":{}}}", count);
+					*num_frames += count;
+				}
+			}
+			Line::Omitted(count) => omitted += count,
+			Line::Boundary => {}
+		}
+	}
+	(frames, omitted)
+}
+
+#[cfg(feature = "std")]
+fn thread_json(thread: &ThreadContext) -> String {
+	let (frames, omitted_frames) = json_frames(&lines(&thread.frame_refs()));
+	let mut json = String::from("{\"thread\":");
+	write_json_string(&mut json, &thread.name);
+	json += &format!(",\"frames\":[{}]", join_frames(&frames));
+	if omitted_frames > 0 {
+		json += &format!(",\"omitted_frames\":{}", omitted_frames);
+	}
+	json += "}";
+	json
+}
+
+#[cfg(feature = "std")]
+fn peer_json(peer: &PeerContext) -> String {
+	let mut json = String::from("{\"version\":");
+	write_json_string(&mut json, &peer.version);
+	json += ",\"context\":";
+	write_json_string(&mut json, &peer.context);
+	json += "}";
+	json
+}
+
+/// The size of the items joined with commas.
+#[cfg(feature = "std")]
+fn json_list_size(items: &[impl AsRef<str>]) -> usize {
+	items.iter().map(|item| item.as_ref().len()).sum::<usize>() + items.len().saturating_sub(1)
+}
+
+#[cfg(feature = "std")]
+fn omitted_frames_size(omitted_frames: usize) -> usize {
+	if omitted_frames > 0 {
+		format!(",\"omitted_frames\":{}", omitted_frames).len()
+	} else {
+		0
+	}
+}
+
+/// Like [`trim_lines`], for the JSON objects of [`json_frames`]: keep the innermost frames
+/// (and the outermost one, if there is room), and return them with the number of frames they represent.
+///
+/// The innermost frame is always kept, even if it does not fit.
+#[cfg(feature = "std")]
+fn trim_json_frames(frames: Vec<(String, usize)>, budget: usize) -> (Vec<(String, usize)>, usize) {
+	let num_frames = frames.len();
+
+	// Each frame takes a comma too, except for one of them:
+	let budget = budget + 1;
+	let outermost = frames
+		.last()
+		.filter(|(json, _)| num_frames > 1 && json.len() < budget / 4)
+		.cloned();
+	let mut remaining = budget - outermost.as_ref().map_or(0, |(json, _)| json.len() + 1);
+
+	let mut trimmed = vec![];
+	for (json, num_frames) in frames.into_iter().take(num_frames - outermost.is_some() as usize) {
+		if json.len() < remaining || trimmed.is_empty() {
+			remaining = remaining.saturating_sub(json.len() + 1);
+			trimmed.push((json, num_frames));
+		} else {
+			break;
+		}
+	}
+	trimmed.extend(outermost);
+
+	let num_kept = trimmed.iter().map(|(_, num_frames)| num_frames).sum();
+	(trimmed, num_kept)
+}
+
+#[cfg(feature = "std")]
+fn join_frames(frames: &[(String, usize)]) -> String {
+	frames.iter().map(|(json, _)| json.as_str()).collect::<Vec<_>>().join(",")
+}
+
+/// A JSON array of frames.
+pub(crate) fn write_json_frames(json: &mut String, frames: &[FrameRef<'_>]) {
+	json.push('[');
+	for (i, frame) in frames.iter().enumerate() {
+		if i > 0 {
+			json.push(',');
+		}
+		write_json_frame(json, frame);
+	}
+	json.push(']');
+}
+
+fn write_json_frame(json: &mut String, frame: &FrameRef<'_>) {
+	json.push_str("{\"module\":");
+	write_json_string(json, frame.module_path);
+	json.push_str(",\"file\":");
+	write_json_string(json, frame.file);
+	json.push_str(&format!(",\"line\":{},\"message\":", frame.line));
+	write_json_string(json, frame.message);
+	json.push_str(",\"data\":");
+	write_json_string(json, frame.data());
+	json.push_str(",\"kind\":\"");
+	json.push_str(frame.kind.as_str());
+	json.push('"');
+	if frame.boundary {
+		json.push_str(",\"boundary\":true");
+	}
+	if let Some(entered) = frame.entered {
+		json.push_str(",\"entered\":\"");
+		write_timestamp(json, entered).ok();
+		json.push('"');
+	}
+	#[cfg(feature = "blame")]
+	if let Some(commit) = crate::metadata::commit_of(frame.module_path, frame.file) {
+		json.push_str(",\"commit\":");
+		write_json_string(json, commit);
+	}
+	json.push('}');
+}
+
+pub(crate) fn write_json_string(json: &mut String, string: &str) {
+	json.push('"');
+	for c in string.chars() {
+		match c {
+			'"' => json.push_str("\\\""),
+			'\\' => json.push_str("\\\\"),
+			'\n' => json.push_str("\\n"),
+			'\r' => json.push_str("\\r"),
+			'\t' => json.push_str("\\t"),
+			c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+			c => json.push(c),
+		}
+	}
+	json.push('"');
+}
+
+#[cfg(feature = "std")]
+fn render(header: &str, lines: &[(String, usize)], sections: &[String], omitted_sections: usize) -> String {
+	let mut text = String::new();
+	if !lines.is_empty() {
+		text += header;
+		for (line, _) in lines {
+			text += line;
+		}
+		text += "\n";
+	}
+	for section in sections {
+		text += section;
+	}
+	if omitted_sections > 0 {
+		text += &format!("({} more section(s) omitted)\n", omitted_sections);
+	}
+	text
+}
+
+/// Time since the unix epoch as UTC with millisecond precision, e.g. `2026-10-16T09:41:07.123Z`.
+pub(crate) fn write_timestamp(writer: &mut dyn core::fmt::Write, since_epoch: Duration) -> core::fmt::Result {
+	let secs = since_epoch.as_secs();
+	let (year, month, day) = civil_from_days(secs / 86_400);
+	let secs_of_day = secs % 86_400;
+	write!(
+		writer,
+		"{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+		year,
+		month,
+		day,
+		secs_of_day / 3600,
+		secs_of_day / 60 % 60,
+		secs_of_day % 60,
+		since_epoch.subsec_millis()
+	)
+}
+
+/// Year, month, and day of the number of days since 1970-01-01, from <http://howardhinnant.github.io/date_algorithms.html>.
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+	let days = days + 719_468;
+	let era = days / 146_097;
+	let day_of_era = days % 146_097;
+	let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+	let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+	let shifted_month = (5 * day_of_year + 2) / 153; // March is 0
+	let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+	let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+	let year = era * 400 + year_of_era + (month <= 2) as u64;
+	(year, month, day)
+}
+
+#[cfg(feature = "serde")]
+pub(crate) fn serialize_timestamp<S: serde::Serializer>(entered: &Option<Duration>, serializer: S) -> Result<S::Ok, S::Error> {
+	let mut text = String::new();
+	if let Some(entered) = entered {
+		write_timestamp(&mut text, *entered).ok();
+	}
+	serializer.serialize_str(&text)
+}
+
+fn omitted_frames_line(count: usize) -> String {
+	format!("  ... {} frames omitted ...\n", count)
+}
+
+/// Keep the innermost lines (and the outermost one, if there is room)
+/// and replace the ones in between with a marker.
+///
+/// Each line comes with the number of frames it represents.
+#[cfg(feature = "std")]
+fn trim_lines(lines: Vec<(String, usize)>, budget: usize) -> Vec<(String, usize)> {
+	let num_lines = lines.len();
+	let num_frames: usize = lines.iter().map(|(_, num_frames)| num_frames).sum();
+
+	// Reserve up to a quarter of the budget for the outermost frame, which usually tells what the program was doing:
+	let outermost = lines
+		.last()
+		.filter(|(line, _)| num_lines > 1 && line.len() <= budget / 4)
+		.cloned();
+	let mut remaining = budget - outermost.as_ref().map_or(0, |(line, _)| line.len());
+
+	let mut trimmed = vec![];
+	for (line, num_frames) in lines.into_iter().take(num_lines - outermost.is_some() as usize) {
+		if line.len() <= remaining {
+			remaining -= line.len();
+			trimmed.push((line, num_frames));
+		} else {
+			if trimmed.is_empty() {
+				// Not even the innermost frame fits, so cut it short:
+				trimmed.push((truncate_line(&line, remaining), num_frames));
+			}
+			break;
+		}
+	}
+
+	let num_kept: usize = trimmed.iter().chain(&outermost).map(|(_, num_frames)| num_frames).sum();
+	if num_kept < num_frames {
+		let num_omitted = num_frames - num_kept;
+		trimmed.push((omitted_frames_line(num_omitted), num_omitted));
+	}
+	trimmed.extend(outermost);
+	trimmed
+}
+
+/// Ends a streamed report that hit [`max_report_size`].
+#[cfg(feature = "std")]
+pub(crate) fn cut_off_line(max_size: usize) -> String {
+	format!("  ... report cut off at {} bytes, see econtext::set_max_report_size ...\n", max_size)
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn truncate_line(line: &str, max_len: usize) -> String {
+	let ellipsis = "…\n";
+	let mut end = max_len.saturating_sub(ellipsis.len()).min(line.len());
+	while !line.is_char_boundary(end) {
+		end -= 1;
+	}
+	format!("{}{}", &line[..end], ellipsis)
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+	use super::*;
+
+	/// One frame per data, each at a different line, innermost first.
+	fn distinct_frames(data: &[String]) -> Vec<FrameRef<'_>> {
+		data.iter()
+			.enumerate()
+			.map(|(i, data)| FrameRef::new("my_crate", "src/lib.rs", i as u32 + 1, "step", data))
+			.collect()
+	}
+
+	/// `num_frames` strings of `data_size` bytes.
+	fn make_data(num_frames: usize, data_size: usize) -> Vec<String> {
+		(0..num_frames).map(|i| format!("{:0>1$}", i, data_size)).collect()
+	}
+
+	/// The number in `... N frames omitted ...`.
+	fn omitted_in_text(text: &str) -> usize {
+		let line = text.lines().find(|line| line.contains("frames omitted")).unwrap();
+		line.trim().trim_start_matches("... ").split(' ').next().unwrap().parse().unwrap()
+	}
+
+	/// The value of `"omitted_frames"`.
+	fn omitted_in_json(json: &str) -> usize {
+		let start = json.find("\"omitted_frames\":").unwrap() + "\"omitted_frames\":".len();
+		json[start..].chars().take_while(char::is_ascii_digit).collect::<String>().parse().unwrap()
+	}
+
+	#[test]
+	fn text_fits_budget() {
+		let data = make_data(50, 40);
+		let report = Report {
+			frames: distinct_frames(&data),
+			..Report::default()
+		};
+
+		let full = report.text_within(Style::Plain, usize::MAX);
+		assert!(!full.contains("omitted"));
+		assert_eq!(full.lines().filter(|line| line.contains("step")).count(), 50);
+
+		let max_size = full.len() / 3;
+		let trimmed = report.text_within(Style::Plain, max_size);
+		assert!(trimmed.len() <= max_size, "{} > {}", trimmed.len(), max_size);
+		let kept = trimmed.lines().filter(|line| line.contains("step")).count();
+		assert_eq!(kept + omitted_in_text(&trimmed), 50);
+
+		// The innermost frames are kept, and so is the outermost one:
+		assert!(trimmed.contains("src/lib.rs:1: step"));
+		assert!(trimmed.contains("src/lib.rs:2: step"));
+		assert!(trimmed.contains("src/lib.rs:50: step"));
+	}
+
+	#[test]
+	fn json_fits_budget() {
+		let data = make_data(50, 40);
+		let report = Report {
+			frames: distinct_frames(&data),
+			..Report::default()
+		};
+
+		let full = report.json_within(usize::MAX);
+		assert!(!full.contains("omitted_frames"));
+		assert_eq!(full.matches("\"line\":").count(), 50);
+
+		let max_size = full.len() / 3;
+		let trimmed = report.json_within(max_size);
+		assert!(trimmed.len() <= max_size, "{} > {}", trimmed.len(), max_size);
+		let kept = trimmed.matches("\"line\":").count();
+		assert_eq!(kept + omitted_in_json(&trimmed), 50);
+		assert!(trimmed.contains("\"line\":1,"));
+		assert!(trimmed.contains("\"line\":50,"));
+		assert!(trimmed.ends_with("}\n"));
+	}
+
+	#[test]
+	fn sections_are_dropped_before_frames() {
+		let data = make_data(5, 10);
+		let thread_data = make_data(100, 40);
+		let report = Report {
+			frames: distinct_frames(&data),
+			other_threads: vec![ThreadContext {
+				name: "'worker'".to_owned(),
+				frames: distinct_frames(&thread_data).iter().map(FrameRef::to_frame).collect(),
+			}],
+			..Report::default()
+		};
+		let max_size = report.text_within(Style::Plain, usize::MAX).len() / 2;
+
+		let text = report.text_within(Style::Plain, max_size);
+		assert!(text.contains("(1 more section(s) omitted)"));
+		assert!(!text.contains("frames omitted"));
+
+		let json = report.json_within(max_size);
+		assert!(json.contains("\"other_threads\":[]"));
+		assert!(!json.contains("omitted_frames"));
+	}
+
+	#[test]
+	fn repeated_sites_are_collapsed() {
+		let data: Vec<String> = (0..5).map(|i| i.to_string()).collect();
+		let mut frames: Vec<FrameRef<'_>> = data
+			.iter()
+			.map(|data| FrameRef::new("my_crate", "src/lib.rs", 7, "recurse", data))
+			.collect();
+		frames.push(FrameRef::new("my_crate", "src/main.rs", 3, "main", &""));
+		let report = Report {
+			frames,
+			..Report::default()
+		};
+
+		let text = report.text_within(Style::Plain, usize::MAX);
+		assert_eq!(text.matches("recurse").count(), 1);
+		assert!(text.contains("  ... repeated 4 more times ...\n"));
+
+		let json = report.json_within(usize::MAX);
+		assert_eq!(json.matches("\"recurse\"").count(), 1);
+		assert!(json.contains("\"repeated\":4}"));
+
+		// Two in a row are not worth collapsing:
+		let pair = [
+			FrameRef::new("my_crate", "src/lib.rs", 7, "recurse", &1),
+			FrameRef::new("my_crate", "src/lib.rs", 7, "recurse", &2),
+		];
+		assert_eq!(lines(&pair).len(), 2);
+	}
+
+	#[test]
+	fn json_escapes_control_characters() {
+		let mut json = String::new();
+		write_json_string(&mut json, "a\"b\\c\nd\re\tf\u{1}g\u{1f}h\u{7f}é");
+		assert_eq!(json, "\"a\\\"b\\\\c\\nd\\re\\tf\\u0001g\\u001fh\u{7f}é\"");
+	}
+
+	#[test]
+	fn single_line_stays_on_one_line() {
+		let data = ["first\nsecond\r\nthird".to_owned(), "plain".to_owned()];
+		let report = Report {
+			frames: vec![
+				FrameRef::formatted("my_crate", "src/lib.rs", 1, "multi\nline", &data[0]),
+				FrameRef::formatted("my_crate", "src/lib.rs", 2, "step", &data[1]),
+			],
+			peers: vec![PeerContext {
+				version: "0.1.0".to_owned(),
+				context: "  my_crate src/old.rs:3: old\n  my_crate src/old.rs:4: older\n".to_owned(),
+			}],
+			..Report::default()
+		};
+
+		for max_size in [usize::MAX, 120, 60] {
+			let line = report.single_line_within(Style::Plain, max_size);
+			assert_eq!(line.matches('\n').count(), 1, "{:?}", line);
+			assert!(line.ends_with('\n'));
+			assert!(!line.contains('\r'));
+		}
+
+		let line = report.single_line_within(Style::Plain, usize::MAX);
+		assert!(line.contains("first\\nsecond\\r\\nthird"));
+		assert!(line.contains(" | "));
+		assert!(line.contains(" || "));
+	}
+}
@@ -0,0 +1,629 @@
+//! The error context stack.
+//!
+//! The stack owns its entries (the data of a scope is moved into it), so nothing on it can dangle.
+//! Each entry gets an id that is unique within the stack, and a [`ScopeGuard`] only pops the entry it pushed
+//! (and anything above it). So a guard that is leaked with `mem::forget` or dropped out of order
+//! (e.g. when a scope is held across an `.await`) at worst leaves the wrong frames on the stack
+//! until an outer guard pops them.
+//!
+//! The `Debug` and `Drop` impls of the data run while the stack is in use.
+//! If they call back into econtext the stack is busy: scopes they enter are not pushed,
+//! and they see no context.
+
+use alloc::{boxed::Box, vec::Vec};
+use core::{
+	fmt::Debug,
+	marker::PhantomData,
+	mem::MaybeUninit,
+	sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use crate::FrameRef;
+
+#[cfg(feature = "std")]
+pub(crate) type Shared<T> = std::sync::Arc<T>;
+
+/// Data up to this many words is stored in the stack itself, without allocating.
+const INLINE_WORDS: usize = 4;
+
+/// An entry on the stack.
+pub(crate) struct Node {
+	/// Unique within the stack, so that a guard only pops its own entry.
+	id: u64,
+
+	kind: NodeKind,
+}
+
+pub(crate) enum NodeKind {
+	/// From the macros or [`crate::scope`].
+	Scope(Scope),
+
+	/// From [`crate::Snapshot::enter`].
+	#[cfg(feature = "std")]
+	Snapshot(Shared<crate::CompactFrames>),
+}
+
+pub(crate) struct Scope {
+	pub module_path: &'static str,
+	pub file: &'static str,
+	pub line: u32,
+	pub message: &'static str,
+	pub kind: crate::FrameKind,
+	pub boundary: bool,
+
+	/// Microseconds since the unix epoch, or `0`. See [`crate::GlobalConfig::with_timestamps`].
+	#[cfg(feature = "std")]
+	pub entered: u64,
+
+	pub data: ScopeData,
+}
+
+/// The type-erased data of a scope.
+pub(crate) enum ScopeData {
+	/// Small data, stored in place.
+	Inline(InlineData),
+
+	/// Larger data.
+	Boxed(Box<dyn Debug>),
+}
+
+pub(crate) struct InlineData {
+	words: MaybeUninit<[usize; INLINE_WORDS]>,
+	vtable: &'static InlineVTable,
+
+	/// The data may be neither `Send` nor `Sync`.
+	_not_send: PhantomData<*const ()>,
+}
+
+struct InlineVTable {
+	/// Turn a pointer to the words into a pointer to the data.
+	as_dyn: unsafe fn(*mut u8) -> *mut dyn Debug,
+
+	needs_drop: bool,
+}
+
+struct VTableFor<T>(PhantomData<T>);
+
+impl<T: Debug + 'static> VTableFor<T> {
+	const VTABLE: InlineVTable = InlineVTable {
+		as_dyn: |words| words.cast::<T>() as *mut dyn Debug,
+		needs_drop: core::mem::needs_drop::<T>(),
+	};
+}
+
+impl ScopeData {
+	#[inline]
+	pub(crate) fn new<T: Debug + 'static>(data: T) -> Self {
+		let fits = core::mem::size_of::<T>() <= core::mem::size_of::<[usize; INLINE_WORDS]>()
+			&& core::mem::align_of::<T>() <= core::mem::align_of::<[usize; INLINE_WORDS]>();
+		if fits {
+			let mut words = MaybeUninit::<[usize; INLINE_WORDS]>::uninit();
+			// Safety: we just checked that a `T` fits, and is aligned.
+			unsafe { words.as_mut_ptr().cast::<T>().write(data) };
+			Self::Inline(InlineData {
+				words,
+				vtable: &VTableFor::<T>::VTABLE,
+				_not_send: PhantomData,
+			})
+		} else {
+			Self::Boxed(Box::new(data))
+		}
+	}
+
+	fn get(&self) -> &dyn Debug {
+		match self {
+			// Safety: the words hold the data of the type of the vtable, and we only read through the pointer.
+			Self::Inline(inline) => unsafe { &*(inline.vtable.as_dyn)(inline.words.as_ptr().cast_mut().cast()) },
+			Self::Boxed(boxed) => &**boxed,
+		}
+	}
+}
+
+impl Drop for InlineData {
+	fn drop(&mut self) {
+		if !self.vtable.needs_drop {
+			return;
+		}
+		// Safety: the words hold the data of the type of the vtable, and it is not used again.
+		unsafe { core::ptr::drop_in_place((self.vtable.as_dyn)(self.words.as_mut_ptr().cast())) };
+	}
+}
+
+impl Node {
+	/// Append the frames of this entry, innermost first.
+	fn frames<'a>(&'a self, frames: &mut Vec<FrameRef<'a>>) {
+		match &self.kind {
+			NodeKind::Scope(scope) => frames.push(scope.frame()),
+			#[cfg(feature = "std")]
+			NodeKind::Snapshot(snapshot) => frames.extend(snapshot.frame_refs()),
+		}
+	}
+
+	#[cfg(feature = "core_dump")]
+	pub(crate) fn num_frames(&self) -> usize {
+		match &self.kind {
+			NodeKind::Scope(_) => 1,
+			NodeKind::Snapshot(snapshot) => snapshot.len(),
+		}
+	}
+
+	/// Write `module file:line: message` of each frame, one per line, outermost first.
+	#[cfg(feature = "core_dump")]
+	pub(crate) fn write_sites(&self, writer: &mut dyn core::fmt::Write) {
+		match &self.kind {
+			NodeKind::Scope(scope) => crate::write_site(writer, scope.module_path, scope.file, scope.line, scope.message),
+			NodeKind::Snapshot(snapshot) => {
+				for frame in snapshot.frame_refs().iter().rev() {
+					crate::write_site(writer, frame.module_path, frame.file, frame.line, frame.message);
+				}
+			}
+		}
+	}
+
+	/// The frames of this entry for the mirror of a registered thread.
+	///
+	/// The data is formatted here, on the thread that owns it, so other threads never touch it.
+	#[cfg(feature = "std")]
+	pub(crate) fn mirror(&self) -> Shared<crate::CompactFrames> {
+		match &self.kind {
+			NodeKind::Scope(scope) => Shared::new(crate::CompactFrames::from_frames(&[scope.frame()])),
+			NodeKind::Snapshot(snapshot) => snapshot.clone(),
+		}
+	}
+}
+
+impl Scope {
+	pub(crate) fn frame(&self) -> FrameRef<'_> {
+		FrameRef {
+			kind: self.kind,
+			boundary: self.boundary,
+			#[cfg(feature = "std")]
+			entered: (self.entered != 0).then(|| core::time::Duration::from_micros(self.entered)),
+			..FrameRef::new(self.module_path, self.file, self.line, self.message, self.data.get())
+		}
+	}
+}
+
+/// Write the message and data of each frame of the current thread on one line, outermost first,
+/// without allocating (except in the `Debug` impls of the data).
+pub(crate) fn write_messages(writer: &mut dyn core::fmt::Write) -> core::fmt::Result {
+	with_stack(|stack, _| {
+		let mut separator = "";
+		let mut write = |message: &str, data: &dyn Debug| {
+			writer.write_str(core::mem::replace(&mut separator, " > "))?;
+			writer.write_str(message)?;
+			core::fmt::Write::write_fmt(&mut Prefixed { writer: &mut *writer, prefix: " " }, format_args!("{:?}", data))
+		};
+		for node in &stack.nodes {
+			match &node.kind {
+				NodeKind::Scope(scope) => write(scope.message, scope.data.get())?,
+				#[cfg(feature = "std")]
+				NodeKind::Snapshot(snapshot) => snapshot.for_each_message(|message, data| write(message, &crate::AsDisplay(data)))?,
+			}
+		}
+		Ok(())
+	})
+	.unwrap_or(Ok(()))
+}
+
+/// Writes `prefix` before the first non-empty string.
+struct Prefixed<'a> {
+	writer: &'a mut dyn core::fmt::Write,
+	prefix: &'static str,
+}
+
+impl core::fmt::Write for Prefixed<'_> {
+	fn write_str(&mut self, s: &str) -> core::fmt::Result {
+		if s.is_empty() {
+			return Ok(());
+		}
+		self.writer.write_str(core::mem::take(&mut self.prefix))?;
+		self.writer.write_str(s)
+	}
+}
+
+/// The frames of the entries, innermost first.
+pub(crate) fn frames(nodes: &[Node]) -> Vec<FrameRef<'_>> {
+	let mut frames = Vec::with_capacity(nodes.len());
+	for node in nodes.iter().rev() {
+		node.frames(&mut frames);
+	}
+	frames
+}
+
+// ----------------------------------------------------------------------------
+
+/// An error context stack: one per thread with `std`, or the single one in a [`crate::StackSlot`] without.
+pub struct Stack {
+	/// Outermost first.
+	nodes: Vec<Node>,
+
+	next_id: u64,
+
+	/// Number of scopes entered above the top of the stack but not pushed, because of [`set_max_depth`].
+	not_recorded: usize,
+}
+
+impl Stack {
+	pub const fn new() -> Self {
+		Self {
+			nodes: Vec::new(),
+			next_id: 1,
+			not_recorded: 0,
+		}
+	}
+
+	#[inline]
+	fn push(&mut self, kind: NodeKind) -> ScopeGuard {
+		self.push_with(|| kind)
+	}
+
+	#[inline]
+	fn push_with(&mut self, kind: impl FnOnce() -> NodeKind) -> ScopeGuard {
+		if self.nodes.len() >= MAX_DEPTH.load(Ordering::Relaxed) {
+			self.not_recorded += 1;
+			return ScopeGuard::NOT_RECORDED;
+		}
+		let id = self.next_id;
+		self.next_id += 1;
+		let index = self.nodes.len();
+		// Write the entry in place, since `Vec::push` copies it through the stack:
+		self.nodes.reserve(1);
+		self.nodes.spare_capacity_mut()[0].write(Node { id, kind: kind() });
+		// Safety: we just initialized the entry after the last one.
+		unsafe { self.nodes.set_len(index + 1) };
+		ScopeGuard {
+			index,
+			id,
+			_not_send: PhantomData,
+		}
+	}
+
+	/// Remove the entry at `index` and everything above it.
+	#[inline]
+	fn truncate(&mut self, index: usize) {
+		self.nodes.truncate(index);
+		// Any scopes above the popped entry are gone too, even if their guards were leaked:
+		self.not_recorded = 0;
+	}
+}
+
+impl Default for Stack {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+#[cfg(feature = "std")]
+struct ThreadStack {
+	stack: core::cell::RefCell<Stack>,
+
+	/// If the thread is registered (see [`crate::register_current_thread`]), this mirrors `stack`
+	/// so that other threads can print our context.
+	shared: core::cell::OnceCell<Shared<crate::threads::SharedStack>>,
+
+	/// See [`crate::disable_for_current_thread`].
+	disabled: core::cell::Cell<bool>,
+}
+
+#[cfg(feature = "std")]
+impl Drop for ThreadStack {
+	fn drop(&mut self) {
+		if let Some(shared) = self.shared.get() {
+			crate::threads::unregister(shared);
+			add_slow_path_reason(false);
+		}
+	}
+}
+
+#[cfg(feature = "std")]
+thread_local! {
+	static THREAD_STACK: ThreadStack = const {
+		ThreadStack {
+			stack: core::cell::RefCell::new(Stack::new()),
+			shared: core::cell::OnceCell::new(),
+			disabled: core::cell::Cell::new(false),
+		}
+	};
+}
+
+/// Call `f` with the stack of the current thread, and its mirror if the thread is registered.
+///
+/// Returns `None` if the stack is busy (we are called from the data), disabled, or gone because the thread is exiting.
+#[cfg(feature = "std")]
+fn with_stack<R>(f: impl FnOnce(&mut Stack, Option<&crate::threads::SharedStack>) -> R) -> Option<R> {
+	THREAD_STACK
+		.try_with(|thread_stack| {
+			if thread_stack.disabled.get() {
+				return None;
+			}
+			let mut stack = thread_stack.stack.try_borrow_mut().ok()?;
+			Some(f(&mut stack, thread_stack.shared.get().map(|shared| &**shared)))
+		})
+		.ok()
+		.flatten()
+}
+
+/// Call `f` with the stack in the [`crate::StackSlot`]. Returns `None` if the stack is busy, or no slot is set.
+#[cfg(not(feature = "std"))]
+fn with_stack<R>(f: impl FnOnce(&mut Stack, Option<&()>) -> R) -> Option<R> {
+	let mut f = Some(f);
+	let mut result = None;
+	crate::stack_slot::slot()?.with_stack(&mut |stack| {
+		if let Some(f) = f.take() {
+			result = Some(f(stack, None));
+		}
+	});
+	result
+}
+
+/// The default of [`set_max_depth`].
+pub const DEFAULT_MAX_DEPTH: usize = 1024;
+
+static MAX_DEPTH: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_DEPTH);
+
+/// Record at most this many nested scopes per thread (default: [`DEFAULT_MAX_DEPTH`]).
+///
+/// Deeper scopes (e.g. from deep recursion in an instrumented dependency) are ignored,
+/// so that the stack, and the report printed on panic, stay bounded. Use `usize::MAX` for no limit.
+/// The context then starts with a `deeper scopes not recorded` frame, with the number of ignored scopes as its data.
+pub fn set_max_depth(max_depth: usize) {
+	MAX_DEPTH.store(max_depth, Ordering::Relaxed);
+}
+
+// ----------------------------------------------------------------------------
+
+/// The number of reasons for pushing and popping to take the slow path, see [`add_slow_path_reason`].
+///
+/// Zero by default (after the first push, and without the `core_dump` feature),
+/// so that a push is a single branch and a `Vec` push.
+static SLOW_PATH: AtomicUsize = AtomicUsize::new(
+	// Registering with the other versions of econtext on the first push, and updating the core dump buffer:
+	cfg!(feature = "std") as usize + cfg!(feature = "core_dump") as usize,
+);
+
+/// Is there anything else to do than pushing to and popping from the stack?
+#[inline]
+pub(crate) fn is_slow_path() -> bool {
+	SLOW_PATH.load(Ordering::Relaxed) != 0
+}
+
+/// Call with `true` when something starts needing the slow path
+/// (e.g. a setting that is turned on, or a thread that is registered), and with `false` when it no longer does.
+pub(crate) fn add_slow_path_reason(add: bool) {
+	if add {
+		SLOW_PATH.fetch_add(1, Ordering::Relaxed);
+	} else {
+		SLOW_PATH.fetch_sub(1, Ordering::Relaxed);
+	}
+}
+
+/// Store a setting that needs the slow path when it is `slow_when`.
+pub(crate) fn store_setting(setting: &AtomicBool, value: bool, slow_when: bool) {
+	if setting.swap(value, Ordering::Relaxed) != value {
+		add_slow_path_reason(value == slow_when);
+	}
+}
+
+/// Push an entry, and return a guard that pops it.
+#[cfg(feature = "std")]
+#[inline]
+pub(crate) fn push(kind: NodeKind) -> ScopeGuard {
+	if is_slow_path() {
+		return push_slow(kind);
+	}
+	push_fast(|| kind)
+}
+
+/// [`push`] when [`is_slow_path`] is `false`.
+#[inline]
+pub(crate) fn push_fast(kind: impl FnOnce() -> NodeKind) -> ScopeGuard {
+	with_stack(|stack, _| stack.push_with(kind)).unwrap_or(ScopeGuard::NOT_PUSHED)
+}
+
+/// [`push`] when [`is_slow_path`] is `true`.
+#[inline(never)]
+pub(crate) fn push_slow(kind: NodeKind) -> ScopeGuard {
+	#[cfg(feature = "std")]
+	crate::peers::ensure_registered();
+
+	with_stack(|stack, _shared| {
+		let guard = stack.push(kind);
+		if guard.id == 0 {
+			return guard;
+		}
+		#[cfg(feature = "std")]
+		if let Some(shared) = _shared {
+			shared.extend(&stack.nodes[guard.index..]);
+		}
+		#[cfg(feature = "core_dump")]
+		crate::core_dump::on_push(&stack.nodes);
+		guard
+	})
+	.unwrap_or(ScopeGuard::NOT_PUSHED)
+}
+
+/// Pop the entry at `index` (and everything above it, e.g. leaked scopes) if it has the given `id`.
+#[inline]
+fn pop(index: usize, id: u64) {
+	if is_slow_path() {
+		pop_slow(index, id);
+		return;
+	}
+	with_stack(|stack, _| {
+		if stack.nodes.get(index).map(|node| node.id) == Some(id) {
+			stack.truncate(index);
+		}
+	});
+}
+
+#[inline(never)]
+fn pop_slow(index: usize, id: u64) {
+	with_stack(|stack, _shared| {
+		if stack.nodes.get(index).map(|node| node.id) != Some(id) {
+			return; // Already popped by an outer guard
+		}
+		#[cfg(feature = "std")]
+		if let NodeKind::Scope(scope) = &stack.nodes[index].kind {
+			crate::breadcrumbs::on_exit(|| scope.frame());
+		}
+		#[cfg(feature = "core_dump")]
+		let num_frames: usize = stack.nodes[index..].iter().map(Node::num_frames).sum();
+		stack.truncate(index);
+		#[cfg(feature = "std")]
+		if let Some(shared) = _shared {
+			shared.truncate(index);
+		}
+		#[cfg(feature = "core_dump")]
+		crate::core_dump::on_pop(&stack.nodes, num_frames);
+	});
+}
+
+/// Call `f` with the frames of the current thread, innermost first.
+///
+/// The frames are empty if the stack is busy or the thread is disabled (see [`crate::disable_for_current_thread`]).
+pub(crate) fn with_frames<R>(f: impl FnOnce(&[FrameRef<'_>]) -> R) -> R {
+	let mut f = Some(f);
+	let result = with_stack(|stack, _| {
+		let mut frames = frames(&stack.nodes);
+		if stack.not_recorded > 0 {
+			let message = "deeper scopes not recorded (see econtext::set_max_depth):";
+			frames.insert(0, FrameRef::new("econtext", file!(), line!(), message, &stack.not_recorded));
+		}
+		f.take().map(|f| f(&frames))
+	})
+	.flatten();
+	match (result, f) {
+		(Some(result), _) => result,
+		(None, Some(f)) => f(&[]),
+		(None, None) => unreachable!("`f` returned a value"),
+	}
+}
+
+/// Remove the entries above the first `len`, outermost first. See [`crate::block_on`].
+#[cfg(feature = "std")]
+pub(crate) fn suspend(len: usize) -> Vec<Node> {
+	with_stack(|stack, shared| {
+		let suspended = stack.nodes.split_off(len.min(stack.nodes.len()));
+		if let Some(shared) = shared {
+			shared.truncate(stack.nodes.len());
+		}
+		#[cfg(feature = "core_dump")]
+		crate::core_dump::on_reset(&stack.nodes);
+		suspended
+	})
+	.unwrap_or_default()
+}
+
+/// Put back entries removed by [`suspend`].
+#[cfg(feature = "std")]
+pub(crate) fn resume(suspended: Vec<Node>) {
+	with_stack(|stack, shared| {
+		if let Some(shared) = shared {
+			shared.extend(&suspended);
+		}
+		stack.nodes.extend(suspended);
+		#[cfg(feature = "core_dump")]
+		crate::core_dump::on_reset(&stack.nodes);
+	});
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn len() -> usize {
+	with_stack(|stack, _| stack.nodes.len()).unwrap_or_default()
+}
+
+/// Mirror the stack of the current thread from now on, unless it already is.
+///
+/// `make_shared` gets the [`Node::mirror`] of the current entries.
+#[cfg(feature = "std")]
+pub(crate) fn share(make_shared: impl FnOnce(Vec<Shared<crate::CompactFrames>>) -> Shared<crate::threads::SharedStack>) {
+	THREAD_STACK
+		.try_with(|thread_stack| {
+			if thread_stack.shared.get().is_some() || thread_stack.disabled.get() {
+				return;
+			}
+			if let Ok(stack) = thread_stack.stack.try_borrow_mut() {
+				let mirror = stack.nodes.iter().map(Node::mirror).collect();
+				thread_stack.shared.set(make_shared(mirror)).ok();
+				add_slow_path_reason(true);
+			}
+		})
+		.ok();
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn disable_for_current_thread() {
+	#[cfg(feature = "core_dump")]
+	with_stack(|_, _| crate::core_dump::on_reset(&[]));
+	THREAD_STACK
+		.try_with(|thread_stack| {
+			thread_stack.disabled.set(true);
+			if let Some(shared) = thread_stack.shared.get() {
+				shared.truncate(0);
+				crate::threads::unregister(shared);
+			}
+		})
+		.ok();
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn is_disabled_for_current_thread() -> bool {
+	THREAD_STACK
+		.try_with(|thread_stack| thread_stack.disabled.get())
+		.unwrap_or(true)
+}
+
+#[cfg(not(feature = "std"))]
+pub(crate) fn is_disabled_for_current_thread() -> bool {
+	false
+}
+
+// ----------------------------------------------------------------------------
+
+/// Pops a scope from the error context stack when dropped.
+///
+/// Returned by [`crate::scope`] and [`crate::DataScope::push`] (which the macros use).
+/// Guards should be dropped on the same thread, in the reverse order they were created.
+/// If not, the stack may show the wrong frames for a while, but nothing worse happens.
+#[must_use = "The scope is popped when the guard is dropped"]
+pub struct ScopeGuard {
+	index: usize,
+
+	/// `0` if nothing was pushed.
+	id: u64,
+
+	/// The guard belongs to the stack of the current thread.
+	_not_send: PhantomData<*const ()>,
+}
+
+impl ScopeGuard {
+	pub(crate) const NOT_PUSHED: Self = Self {
+		index: 0,
+		id: 0,
+		_not_send: PhantomData,
+	};
+
+	/// Not pushed because of [`set_max_depth`], but counted in [`Stack::not_recorded`].
+	const NOT_RECORDED: Self = Self {
+		index: usize::MAX,
+		id: 0,
+		_not_send: PhantomData,
+	};
+}
+
+impl Drop for ScopeGuard {
+	#[inline]
+	fn drop(&mut self) {
+		if self.id != 0 {
+			pop(self.index, self.id);
+		} else if self.index == usize::MAX {
+			forget_not_recorded();
+		}
+	}
+}
+
+#[cold]
+fn forget_not_recorded() {
+	with_stack(|stack, _| stack.not_recorded = stack.not_recorded.saturating_sub(1));
+}
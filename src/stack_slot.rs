@@ -0,0 +1,106 @@
+//! Where the stack lives without the `std` feature, since there are no thread-locals.
+
+use core::cell::{Cell, UnsafeCell};
+
+use crate::Stack;
+
+/// Stores the error context stack when the `std` feature is off.
+///
+/// With `std` each thread has its own stack. Without it there is a single stack,
+/// stored in the slot given to [`set_stack_slot`]. Until one is set, nothing is recorded.
+///
+/// Scopes entered by an interrupt handler are popped before it returns, so interrupts can share
+/// the stack of the code they interrupt, as long as `with_stack` can't be interrupted.
+/// On a single-core target that can be done with a critical section
+/// (which is then also held while the context is formatted, e.g. in [`crate::write_econtext`]):
+///
+/// ``` ignore
+/// use core::cell::RefCell;
+/// use critical_section::Mutex;
+///
+/// struct CriticalSectionSlot(Mutex<RefCell<econtext::Stack>>);
+///
+/// impl econtext::StackSlot for CriticalSectionSlot {
+/// 	fn with_stack(&self, f: &mut dyn FnMut(&mut econtext::Stack)) {
+/// 		critical_section::with(|cs| {
+/// 			if let Ok(mut stack) = self.0.borrow(cs).try_borrow_mut() {
+/// 				f(&mut stack);
+/// 			}
+/// 		})
+/// 	}
+/// }
+///
+/// static SLOT: CriticalSectionSlot = CriticalSectionSlot(Mutex::new(RefCell::new(econtext::Stack::new())));
+///
+/// fn main() {
+/// 	unsafe { econtext::set_stack_slot(&SLOT) };
+/// }
+/// ```
+///
+/// On multi-core targets, keep one stack per core in the slot.
+/// The simplest slot is a [`SingleCoreSlot`].
+pub trait StackSlot: Sync {
+	/// Call `f` with the stack, which nothing else may access until `f` returns.
+	///
+	/// `f` may run the `Debug` and `Drop` impls of the data, which may call back into econtext.
+	/// Don't call `f` while a previous call is still running: just return.
+	fn with_stack(&self, f: &mut dyn FnMut(&mut Stack));
+}
+
+/// The simplest [`StackSlot`]: a plain static cell, without any synchronization.
+///
+/// ```
+/// # #[cfg(not(feature = "std"))]
+/// # {
+/// // Safety: single-core, and no interrupt handler enters scopes.
+/// static SLOT: econtext::SingleCoreSlot = unsafe { econtext::SingleCoreSlot::new() };
+/// unsafe { econtext::set_stack_slot(&SLOT) };
+/// # }
+/// ```
+pub struct SingleCoreSlot {
+	stack: UnsafeCell<Stack>,
+	busy: Cell<bool>,
+}
+
+impl SingleCoreSlot {
+	/// # Safety
+	/// The slot must only ever be used from a single thread of execution:
+	/// a single-core target where scopes are never entered from interrupt handlers
+	/// (or from only one of them, which does not preempt other code that enters scopes).
+	pub const unsafe fn new() -> Self {
+		Self {
+			stack: UnsafeCell::new(Stack::new()),
+			busy: Cell::new(false),
+		}
+	}
+}
+
+// Safety: the caller of `SingleCoreSlot::new` promised that there is only one thread of execution using it.
+unsafe impl Sync for SingleCoreSlot {}
+
+impl StackSlot for SingleCoreSlot {
+	fn with_stack(&self, f: &mut dyn FnMut(&mut Stack)) {
+		if self.busy.replace(true) {
+			return;
+		}
+		// Safety: there is only one thread of execution, and `busy` keeps `f` from re-entering.
+		f(unsafe { &mut *self.stack.get() });
+		self.busy.set(false);
+	}
+}
+
+static mut STACK_SLOT: Option<&dyn StackSlot> = None;
+
+/// Where to store the error context stack. See [`StackSlot`].
+///
+/// # Safety
+/// Call this before entering any scope, and not at the same time as anything else in econtext
+/// (e.g. first thing in `main`, before interrupts are enabled).
+pub unsafe fn set_stack_slot(slot: &'static dyn StackSlot) {
+	STACK_SLOT = Some(slot);
+}
+
+pub(crate) fn slot() -> Option<&'static dyn StackSlot> {
+	// Safety: only written by `set_stack_slot`, which must not race with this.
+	unsafe { STACK_SLOT }
+}
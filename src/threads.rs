@@ -0,0 +1,235 @@
+//! Carrying error context across threads.
+
+use std::{
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc, Mutex, MutexGuard, TryLockError,
+	},
+	thread::ThreadId,
+};
+
+use crate::{
+	report::ThreadContext,
+	stack::{self, Node, NodeKind, ScopeGuard},
+	CompactFrames, Frame,
+};
+
+/// Lock a mutex, ignoring poisoning (we are often called while panicking).
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+	mutex.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// ----------------------------------------------------------------------------
+
+/// A copy of the stack of a registered thread, readable from other threads.
+///
+/// Each entry is the frames of one entry of the stack, with the data already formatted (see [`Node::mirror`]).
+pub(crate) struct SharedStack {
+	id: ThreadId,
+	nodes: Mutex<Vec<Arc<CompactFrames>>>,
+}
+
+impl SharedStack {
+	pub(crate) fn truncate(&self, len: usize) {
+		lock(&self.nodes).truncate(len);
+	}
+
+	pub(crate) fn extend(&self, nodes: &[Node]) {
+		let mirrored: Vec<_> = nodes.iter().map(|node| node.mirror()).collect();
+		lock(&self.nodes).extend(mirrored);
+	}
+}
+
+struct RegisteredThread {
+	name: Option<String>,
+	shared: Arc<SharedStack>,
+}
+
+static REGISTRY: Mutex<Vec<RegisteredThread>> = Mutex::new(Vec::new());
+
+static PRINT_ALL_THREADS: AtomicBool = AtomicBool::new(false);
+
+/// Register the current thread so that its error context is printed
+/// when another thread panics (if [`set_print_all_threads`] is on).
+///
+/// The thread is unregistered automatically when it exits.
+/// Threads started with [`spawn`] are registered automatically.
+///
+/// This makes entering and leaving scopes on this thread slower,
+/// since their data is formatted when they are entered (so other threads never touch it),
+/// and they need to take an (uncontended) lock.
+/// So other threads see the data as it was when the scope was entered.
+pub fn register_current_thread() {
+	stack::share(|nodes| {
+		let thread = std::thread::current();
+		let shared = Arc::new(SharedStack {
+			id: thread.id(),
+			nodes: Mutex::new(nodes),
+		});
+		lock(&REGISTRY).push(RegisteredThread {
+			name: thread.name().map(ToOwned::to_owned),
+			shared: shared.clone(),
+		});
+		shared
+	});
+}
+
+pub(crate) fn unregister(shared: &SharedStack) {
+	lock(&REGISTRY).retain(|thread| thread.shared.id != shared.id);
+}
+
+/// If `true`, [`crate::print_econtext`] (and thus the panic hook)
+/// will also print the error context of all other registered threads.
+///
+/// Off by default. See [`register_current_thread`].
+pub fn set_print_all_threads(print_all_threads: bool) {
+	PRINT_ALL_THREADS.store(print_all_threads, Ordering::Relaxed);
+}
+
+pub(crate) fn print_all_threads() -> bool {
+	PRINT_ALL_THREADS.load(Ordering::Relaxed)
+}
+
+/// Call `f` with the error context of each registered thread except the current one, as soon as it has been captured.
+pub(crate) fn for_each_other_thread(mut f: impl FnMut(ThreadContext)) {
+	// Don't hold the lock while calling `f`, which may e.g. spawn a thread (or wait for one that is exiting):
+	let threads: Vec<(Option<String>, Arc<SharedStack>)> = {
+		let registry = match REGISTRY.try_lock() {
+			Ok(registry) => registry,
+			Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner(),
+			Err(TryLockError::WouldBlock) => return, // Panic while printing
+		};
+		let current = std::thread::current().id();
+		registry
+			.iter()
+			.filter(|thread| thread.shared.id != current)
+			.map(|thread| (thread.name.clone(), thread.shared.clone()))
+			.collect()
+	};
+
+	for (name, shared) in threads {
+		let nodes: Vec<Arc<CompactFrames>> = lock(&shared.nodes).clone();
+		let frames: Vec<Frame> = nodes.iter().rev().flat_map(|frames| frames.frames()).collect();
+		if !frames.is_empty() {
+			f(ThreadContext {
+				name: crate::metadata::thread_label(name.as_deref(), shared.id),
+				frames,
+			});
+		}
+	}
+}
+
+// ----------------------------------------------------------------------------
+
+/// An owned copy of the error context of a thread.
+///
+/// Capture it on one thread and [`Self::enter`] it on another thread (e.g. in a thread pool)
+/// so that a panic there prints the context of the thread that started the work.
+///
+/// ```
+/// econtext::econtext!("Processing images");
+/// let snapshot = econtext::Snapshot::capture();
+/// std::thread::spawn(move || {
+/// 	let _guard = snapshot.enter();
+/// 	assert!(econtext::econtext_string().contains("Processing images"));
+/// })
+/// .join()
+/// .unwrap();
+/// ```
+///
+/// The frames are stored as [`CompactFrames`], so keeping many snapshots around is cheap.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Snapshot {
+	frames: Arc<CompactFrames>,
+}
+
+impl Snapshot {
+	/// Capture the error context of the current thread.
+	pub fn capture() -> Self {
+		Self {
+			frames: Arc::new(CompactFrames::capture()),
+		}
+	}
+
+	/// The captured frames, innermost first.
+	pub fn frames(&self) -> Vec<Frame> {
+		self.frames.frames()
+	}
+
+	pub fn compact_frames(&self) -> &CompactFrames {
+		&self.frames
+	}
+
+	/// Push the captured frames onto the error context stack of the current thread
+	/// until the returned guard is dropped.
+	///
+	/// Does nothing if econtext is off, see [`crate::set_enabled`] and [`crate::disable_for_current_thread`].
+	pub fn enter(&self) -> SnapshotGuard {
+		let guard = if crate::is_enabled() {
+			stack::push(NodeKind::Snapshot(self.frames.clone()))
+		} else {
+			ScopeGuard::NOT_PUSHED
+		};
+		SnapshotGuard { _guard: guard }
+	}
+}
+
+/// Returned by [`Snapshot::enter`]. Pops the snapshot from the stack when dropped.
+///
+/// Guards should be dropped in the reverse order they were created, see [`ScopeGuard`].
+pub struct SnapshotGuard {
+	_guard: ScopeGuard,
+}
+
+/// Like [`std::thread::spawn`], but the new thread starts with a copy of the error context
+/// of the current thread, and is registered with [`register_current_thread`].
+pub fn spawn<F, T>(f: F) -> std::thread::JoinHandle<T>
+where
+	F: FnOnce() -> T + Send + 'static,
+	T: Send + 'static,
+{
+	let snapshot = Snapshot::capture();
+	std::thread::spawn(move || {
+		register_current_thread();
+		let _guard = snapshot.enter();
+		f()
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::mpsc;
+
+	use crate::report::Report;
+
+	fn other_threads_text() -> String {
+		let report = Report::capture_streaming(&[], &mut |_, _| {});
+		report.other_threads.iter().flat_map(|thread| &thread.frames).map(|frame| format!("{:?}\n", frame)).collect()
+	}
+
+	#[test]
+	fn disable_for_current_thread_unregisters() {
+		super::set_print_all_threads(true);
+
+		let (entered_tx, entered_rx) = mpsc::channel();
+		let (disabled_tx, disabled_rx) = mpsc::channel();
+		let (done_tx, done_rx) = mpsc::channel::<()>();
+		let thread = super::spawn(move || {
+			crate::econtext!("Loading plugin secret_plugin");
+			entered_tx.send(()).unwrap();
+			crate::disable_for_current_thread();
+			crate::register_current_thread();
+			disabled_tx.send(()).unwrap();
+			done_rx.recv().ok();
+		});
+
+		entered_rx.recv().unwrap();
+		assert!(other_threads_text().contains("secret_plugin"));
+
+		disabled_rx.recv().unwrap();
+		assert!(!other_threads_text().contains("secret_plugin"));
+
+		done_tx.send(()).unwrap();
+		thread.join().unwrap();
+	}
+}
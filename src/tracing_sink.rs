@@ -0,0 +1,60 @@
+//! Emitting reports as `tracing` events, with the `tracing` feature.
+
+use crate::{report::write_json_frames, Frame, FrameRef, Sink};
+
+/// A [`Sink`] that emits reports as `ERROR` events with the `econtext` target,
+/// so they end up in the same place (journald, OTLP, …) as the rest of your telemetry.
+///
+/// ```
+/// econtext::add_output(econtext::TracingSink::new(), econtext::Format::Text);
+/// # econtext::set_output(econtext::Stderr);
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TracingSink {
+	per_frame: bool,
+}
+
+impl TracingSink {
+	/// One event with the whole report as the message (formatted according to the format given to
+	/// [`crate::add_output`]), and the frames of the current thread as JSON (see [`crate::econtext_json`])
+	/// in the `frames` field.
+	pub fn new() -> Self {
+		Self { per_frame: false }
+	}
+
+	/// One event per frame of the current thread, innermost first, with the message and data of the frame
+	/// as the message, and `module`, `file`, and `line` fields.
+	///
+	/// The context of other threads and other versions of econtext is not included.
+	/// A report written without its frames (with [`Sink::write`]) is one event, without the `frames` field.
+	pub fn per_frame() -> Self {
+		Self { per_frame: true }
+	}
+}
+
+impl Sink for TracingSink {
+	fn write(&self, report: &str) {
+		tracing::error!(target: "econtext", "{}", report.trim_end());
+	}
+
+	fn write_report(&self, report: &str, frames: &[Frame]) {
+		if !self.per_frame {
+			let mut json = String::new();
+			write_json_frames(&mut json, &frames.iter().map(FrameRef::from).collect::<Vec<_>>());
+			tracing::error!(target: "econtext", frames = %json, "{}", report.trim_end());
+			return;
+		}
+
+		for frame in frames {
+			tracing::error!(
+				target: "econtext",
+				module = frame.module_path,
+				file = frame.file,
+				line = frame.line,
+				"{} {}",
+				frame.message,
+				frame.data
+			);
+		}
+	}
+}
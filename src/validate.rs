@@ -0,0 +1,107 @@
+//! Checking the data of scopes in debug builds.
+
+use std::{
+	fmt::Debug,
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc, RwLock,
+	},
+};
+
+type Validator = Arc<dyn Fn(&str) -> Result<(), String> + Send + Sync>;
+
+enum Target {
+	Key(&'static str),
+	Message(&'static str),
+}
+
+static ANY_KEY_VALIDATORS: AtomicBool = AtomicBool::new(false);
+static ANY_MESSAGE_VALIDATORS: AtomicBool = AtomicBool::new(false);
+static VALIDATORS: RwLock<Vec<(Target, Validator)>> = RwLock::new(Vec::new());
+
+/// Check the values of a key whenever a scope is entered, in debug builds.
+///
+/// `validator` gets the formatted value, and returns why it is invalid.
+/// This applies to named values, e.g. `econtext_data!("handling", request_id = id)`,
+/// and keys are matched case-insensitively (like [`crate::set_redacted_keys`]).
+///
+/// ```
+/// econtext::add_key_validator("request_id", |value| {
+/// 	if value.len() == 38 && value.matches('-').count() == 4 {
+/// 		Ok(())
+/// 	} else {
+/// 		Err("request_id must be a quoted UUID".to_owned())
+/// 	}
+/// });
+/// econtext::econtext_data!("handling", request_id = "67e55044-10b1-426f-9247-bb680e5fe0c8");
+/// ```
+///
+/// An invalid value panics, like a failed `debug_assert!`, so that instrumentation drift is caught in tests.
+/// In release builds (without `debug_assertions`) validators are never called.
+pub fn add_key_validator(key: &'static str, validator: impl Fn(&str) -> Result<(), String> + Send + Sync + 'static) {
+	add(Target::Key(key), Arc::new(validator));
+	ANY_KEY_VALIDATORS.store(true, Ordering::Relaxed);
+}
+
+/// Check the data of all scopes with this message whenever one is entered, in debug builds.
+///
+/// `validator` gets the formatted data. See [`add_key_validator`].
+pub fn add_message_validator(message: &'static str, validator: impl Fn(&str) -> Result<(), String> + Send + Sync + 'static) {
+	add(Target::Message(message), Arc::new(validator));
+	if cfg!(debug_assertions) {
+		crate::stack::store_setting(&ANY_MESSAGE_VALIDATORS, true, true);
+	}
+}
+
+fn add(target: Target, validator: Validator) {
+	VALIDATORS
+		.write()
+		.unwrap_or_else(|poisoned| poisoned.into_inner())
+		.push((target, validator));
+}
+
+/// The validators for `target`, so that they are called without holding the lock.
+fn validators(matches: impl Fn(&Target) -> bool) -> Vec<Validator> {
+	VALIDATORS
+		.read()
+		.unwrap_or_else(|poisoned| poisoned.into_inner())
+		.iter()
+		.filter(|(target, _)| matches(target))
+		.map(|(_, validator)| validator.clone())
+		.collect()
+}
+
+pub(crate) fn any_key_validators() -> bool {
+	cfg!(debug_assertions) && ANY_KEY_VALIDATORS.load(Ordering::Relaxed)
+}
+
+#[track_caller]
+pub(crate) fn check_key(key: &'static str, value: &dyn Debug) {
+	let validators = validators(|target| matches!(target, Target::Key(k) if k.eq_ignore_ascii_case(key)));
+	if validators.is_empty() {
+		return;
+	}
+	let value = format!("{:?}", value);
+	for validator in validators {
+		if let Err(err) = validator(&value) {
+			let location = std::panic::Location::caller();
+			panic!("econtext: invalid value of `{}` at {}:{}: {}: {}", key, location.file(), location.line(), err, value);
+		}
+	}
+}
+
+pub(crate) fn check_message(message: &'static str, file: &str, line: u32, data: &dyn Debug) {
+	if !cfg!(debug_assertions) || !ANY_MESSAGE_VALIDATORS.load(Ordering::Relaxed) {
+		return;
+	}
+	let validators = validators(|target| matches!(target, Target::Message(m) if *m == message));
+	if validators.is_empty() {
+		return;
+	}
+	let data = format!("{:?}", data);
+	for validator in validators {
+		if let Err(err) = validator(&data) {
+			panic!("econtext: invalid data of `{}` at {}:{}: {}: {}", message, file, line, err, data);
+		}
+	}
+}
@@ -0,0 +1,31 @@
+//! Printing the error context in the browser, with the `wasm` feature.
+//!
+//! On `wasm32-unknown-unknown` there is no stderr, so [`Console`] is the default [`crate::Sink`].
+//!
+//! To also get the panic message and a stack trace from [`console_error_panic_hook`](https://docs.rs/console_error_panic_hook),
+//! install it first, then call [`crate::add_panic_hook`], which calls it after printing the error context:
+//!
+//! ``` ignore
+//! console_error_panic_hook::set_once();
+//! econtext::add_panic_hook();
+//! ```
+
+use wasm_bindgen::prelude::*;
+
+/// A [`crate::Sink`] that writes to the browser console with `console.error`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Console;
+
+impl crate::Sink for Console {
+	fn write(&self, report: &str) {
+		web_sys::console::error_1(&JsValue::from_str(report.trim_end()));
+	}
+}
+
+/// The error context of the current thread, as in [`crate::econtext_string`].
+///
+/// Exported to JavaScript as `econtextString()`, e.g. for attaching it to telemetry in an `error` event handler.
+#[wasm_bindgen(js_name = econtextString)]
+pub fn econtext_string_js() -> String {
+	crate::econtext_string()
+}